@@ -0,0 +1,173 @@
+use std::io::{self, Write};
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+use crate::query::Notes;
+use crate::tasks::Task;
+
+/// File name of the board page, in the root of the outdir
+pub const BOARD_PAGE: &str = "board.html";
+
+/// An item on the board
+pub enum Card<'a> {
+    /// A note with the board field in its front matter
+    Note(&'a Note),
+    /// A task with a `field:value` word in its text
+    Task(Task<'a>),
+}
+
+impl<'a> Card<'a> {
+    pub fn note(&self) -> &'a Note {
+        match self {
+            Card::Note(note) => note,
+            Card::Task(task) => task.note,
+        }
+    }
+}
+
+/// Notes and tasks grouped into columns by the value of a metadata field
+///
+/// The columns are the configured ones, in order, followed by any other
+/// values found, sorted by name.
+pub struct Board<'a> {
+    pub field: String,
+    pub columns: Vec<(String, Vec<Card<'a>>)>,
+}
+
+impl<'a> Board<'a> {
+    /// Board of `notes`, and of the tasks in them, using the field and
+    /// columns of the notebook config
+    pub fn build(notebook: &'a Notebook, notes: Notes<'a>) -> Self {
+        let field = notebook.board_field();
+        let mut board = Self {
+            field: String::from(field),
+            columns: notebook.board_columns().iter()
+                .map(|column| (column.clone(), vec![]))
+                .collect(),
+        };
+        let configured = board.columns.len();
+
+        let options = notebook.compiler().parse_options;
+        for note in notes {
+            if let Some(value) = note.meta(field).filter(|x| !x.is_empty()) {
+                board.add(&value, Card::Note(note));
+            }
+            for task in Task::find(note, options) {
+                if let Some(value) = task_field(&task, field) {
+                    board.add(&value, Card::Task(task));
+                }
+            }
+        }
+
+        board.columns[configured..].sort_by(|a, b| a.0.cmp(&b.0));
+        board
+    }
+
+    fn add(&mut self, column: &str, card: Card<'a>) {
+        match self.columns.iter_mut().find(|(name, _)| name == column) {
+            Some((_, cards)) => cards.push(card),
+            None => self.columns.push((String::from(column), vec![card])),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(|(_, cards)| cards.is_empty())
+    }
+
+    /// Text of a card, without the `field:value` word for tasks
+    pub fn title(&self, card: &Card) -> String {
+        match card {
+            Card::Note(note) => String::from(note.title()),
+            Card::Task(task) => {
+                let prefix = format!("{}:", self.field);
+                task.description().split_whitespace()
+                    .filter(|word| !word.starts_with(&prefix))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            },
+        }
+    }
+
+    /// Print the columns and their cards
+    pub fn print(&self) {
+        for (column, cards) in &self.columns {
+            println!("{} ({})", column, cards.len());
+            for card in cards {
+                match card {
+                    Card::Note(note) => println!("    {}\t{}", note.id(),
+                                                 self.title(card)),
+                    Card::Task(task) => println!("    {}:{}\t[{}] {}",
+                        task.note.id(), task.position.0,
+                        if task.done { 'x' } else { ' ' }, self.title(card)),
+                }
+            }
+        }
+    }
+}
+
+/// Value of a `field:value` word in the text of `task`
+fn task_field(task: &Task, field: &str) -> Option<String> {
+    let prefix = format!("{}:", field);
+    task.text.split_whitespace()
+        .find_map(|word| word.strip_prefix(&prefix))
+        .map(|value| value.trim_end_matches([',', '.', ';', ')']))
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+}
+
+/// Writes the board of the published notes as a page with a column per value
+pub struct BoardPage<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> BoardPage<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// Write the board, also when it is empty so an old one does not stay
+    /// around
+    pub fn write_all(&self) -> io::Result<()> {
+        let board = Board::build(self.notebook, self.notebook.notes().published());
+        let outfile = self.notebook.outdir().join(BOARD_PAGE);
+        println!("Writing to {}", outfile.display());
+        fs::create_dir_all(self.notebook.outdir())?;
+        let page = self.notebook.compiler()
+            .to_page("Board", &render(&board), 0, &[], self.notebook);
+        fs::File::create(&outfile)?.write_all(page.as_bytes())
+    }
+}
+
+fn render(board: &Board) -> String {
+    let mut html = String::from("<h1>Board</h1>\n");
+    if board.is_empty() {
+        html.push_str(&format!("<p>No notes or tasks with a {} field.</p>\n",
+                               escape(&board.field)));
+    }
+    html.push_str("<div class=\"board\">\n");
+    for (column, cards) in &board.columns {
+        html.push_str(&format!(concat!(
+            "<section class=\"board-column\">\n",
+            "<h2>{} <span class=\"count\">{}</span></h2>\n",
+            "<ul>\n"),
+            escape(column), cards.len()));
+
+        for card in cards {
+            let title = escape(&board.title(card));
+            html.push_str(&match card {
+                Card::Note(note) => format!(
+                    "<li class=\"card\"><a href=\"{}.html\">{}</a></li>\n",
+                    escape(note.id()), title),
+                Card::Task(task) => format!(concat!(
+                    "<li class=\"card task{}\"><a href=\"{}.html#task-{}\">{}</a> ",
+                    "<span class=\"task-note\">{}</span></li>\n"),
+                    if task.done { " done" } else { "" }, escape(task.note.id()),
+                    task.number, title, escape(task.note.title())),
+            });
+        }
+        html.push_str("</ul>\n</section>\n");
+    }
+    html.push_str("</div>\n");
+    html
+}
@@ -0,0 +1,347 @@
+use pulldown_cmark::{BrokenLink, Event, Tag};
+use pulldown_cmark::Parser as CmarkParser;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::fmt;
+use std::fs;
+
+use crate::{Note, Notebook, Resolved};
+use crate::compiler::Asset;
+use crate::links::{find_blocks, local_path, normalize};
+use crate::query::Notes;
+
+/// A problem found by `Checker`
+pub struct Diagnostic {
+    pub path: PathBuf,
+    /// Line and column, both starting at 1. `None` for problems with the
+    /// file as a whole.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}",
+                self.path.display(), line, column, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Body of a note, used to turn byte offsets into positions in the file
+pub(crate) struct Source {
+    pub body: String,
+    /// Number of front matter lines stripped from the body
+    header: usize,
+}
+
+impl Source {
+    pub fn read(note: &Note) -> Self {
+        let body = note.read();
+        let full = fs::read_to_string(&note.path)
+            .map(|x| note.format.to_markdown(&x))
+            .unwrap_or_default();
+        let header = full.lines().count().saturating_sub(body.lines().count());
+        Self { body, header }
+    }
+
+    /// Line and column of byte `offset` in the body. For formats other than
+    /// markdown this is the position in the converted markdown.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.body[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (self.header + line, column)
+    }
+}
+
+/// Finds broken links, missing files, orphan notes, duplicate titles and
+/// front matter that does not match the schema
+pub struct Checker<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// Check the selected `notes`. Links are resolved against the whole
+    /// notebook, so orphans and duplicate titles are found across all notes
+    /// but only reported for the selected ones.
+    pub fn check(&self, notes: Notes) -> Vec<Diagnostic> {
+        let mut output = self.check_assets();
+        let mut linked = HashSet::new();
+
+        let mut ids: Vec<&str> = self.notebook.note_ids();
+        ids.sort_unstable();
+        for id in ids {
+            let note = self.notebook.get(id).unwrap();
+            let source = Source::read(note);
+            let mut problems = vec![];
+
+            self.check_wikilinks(note, &source, &mut problems, &mut linked);
+            self.check_files(note, &source, &mut problems, &mut linked);
+            problems.extend(self.notebook.check_schema(note));
+
+            output.push((id, problems));
+        }
+
+        let selected: HashSet<&str> = notes.map(|note| note.id()).collect();
+        let mut output: Vec<Diagnostic> = output.into_iter()
+            .filter(|(id, _)| id.is_empty() || selected.contains(id))
+            .flat_map(|(_, problems)| problems)
+            .collect();
+
+        for note in self.orphans(&linked) {
+            if selected.contains(note.id()) {
+                output.push(Diagnostic {
+                    path: note.path.clone(),
+                    position: None,
+                    message: String::from("orphan note, no other note links to it"),
+                });
+            }
+        }
+        output.extend(self.duplicate_titles().into_iter()
+            .filter(|(id, _)| selected.contains(id))
+            .map(|(_, diagnostic)| diagnostic));
+
+        output.sort_by(|a, b| (&a.path, a.position).cmp(&(&b.path, b.position)));
+        output
+    }
+
+    /// Stylesheets and scripts that are not in the basedir. These are listed
+    /// under an empty note id.
+    fn check_assets(&self) -> Vec<(&'a str, Vec<Diagnostic>)> {
+        let basedir = self.notebook.basedir();
+        let problems = self.notebook.compiler().assets.iter()
+            .filter_map(|asset| match asset {
+                Asset::Js(path) | Asset::Css(path) => Some(path),
+                Asset::BuiltJs(_) => None,
+            })
+            .filter(|path| !basedir.join(path).exists())
+            .map(|path| Diagnostic {
+                path: basedir.join(path),
+                position: None,
+                message: String::from("asset not found"),
+            })
+            .collect();
+        vec![("", problems)]
+    }
+
+    /// Wikilinks that do not resolve, or resolve to a note that is not
+    /// published
+    fn check_wikilinks(&self, note: &'a Note, source: &Source,
+                       output: &mut Vec<Diagnostic>,
+                       linked: &mut HashSet<&'a str>) {
+        let options = self.notebook.compiler().parse_options;
+        for link in crate::links::find_links(&source.body, options) {
+            let block = link.fragment.as_deref()
+                .and_then(|x| x.strip_prefix('^'));
+
+            // Links to a section or block of the note itself
+            if link.target.is_empty() {
+                if let Some(block) = block.filter(|x| !self.has_block(note, x)) {
+                    output.push(Diagnostic {
+                        path: note.path.clone(),
+                        position: Some(source.position(link.span.start)),
+                        message: format!("link to missing block ^{}", block),
+                    });
+                }
+                continue;
+            }
+
+            let message = match self.notebook.resolve(&link.target) {
+                Resolved::Found(target) => {
+                    if target.id() != note.id() {
+                        linked.insert(target.id());
+                    }
+                    if let Some(block) = block.filter(|x| !self.has_block(target, x)) {
+                        format!("link [{}] to missing block ^{} in {}",
+                                link.target, block, target.id())
+                    } else if !self.notebook.is_published(note)
+                        || self.notebook.is_published(target)
+                    {
+                        continue;
+                    } else {
+                        format!("link [{}] to unpublished note {}", link.target,
+                                target.id())
+                    }
+                },
+                Resolved::Ambiguous(candidates) =>
+                    format!("ambiguous link [{}] matches {}", link.target,
+                            candidates.join(", ")),
+                Resolved::Missing => match self.ignored(&link.target) {
+                    Some(path) => format!("link [{}] to ignored {}",
+                                          link.target, path.display()),
+                    None => format!("unresolved link [{}]", link.target),
+                },
+            };
+
+            output.push(Diagnostic {
+                path: note.path.clone(),
+                position: Some(source.position(link.span.start)),
+                message,
+            });
+        }
+    }
+
+    /// Check whether `note` has a block with a `^id` marker
+    fn has_block(&self, note: &Note, id: &str) -> bool {
+        find_blocks(&note.read(), self.notebook.compiler().parse_options)
+            .iter().any(|(block, _)| block == id)
+    }
+
+    /// Images and links to local files that do not exist
+    fn check_files(&self, note: &'a Note, source: &Source,
+                   output: &mut Vec<Diagnostic>,
+                   linked: &mut HashSet<&'a str>) {
+        // Leave wikilinks as text, they are checked separately
+        let func = &mut |_: BrokenLink| None;
+        let parser = CmarkParser::new_with_broken_link_callback(
+            &source.body, self.notebook.compiler().parse_options, Some(func));
+
+        let dir = note.path.parent().unwrap_or_else(|| Path::new(""));
+        for (event, range) in parser.into_offset_iter() {
+            let (kind, dest) = match event {
+                Event::Start(Tag::Image(_, dest, _)) => ("image", dest),
+                Event::Start(Tag::Link(_, dest, _)) => ("link", dest),
+                _ => continue,
+            };
+            let file = match local_path(&dest) {
+                Some(file) if file.starts_with('/') =>
+                    normalize(&self.notebook.basedir().join(&file[1..])),
+                Some(file) => normalize(&dir.join(file)),
+                None => continue,
+            };
+
+            // Links to the compiled page of a note
+            if file.extension().is_some_and(|x| x == "html") {
+                let id = self.notebook.note_id(&file.with_extension(""));
+                if let Some(target) = self.notebook.get(&id) {
+                    linked.insert(target.id());
+                    continue;
+                }
+            }
+            if file.exists() {
+                let id = self.notebook.note_id(&file);
+                if let Some(target) = self.notebook.get(&id) {
+                    linked.insert(target.id());
+                }
+                continue;
+            }
+
+            output.push(Diagnostic {
+                path: note.path.clone(),
+                position: Some(source.position(range.start)),
+                message: format!("{} {} not found", kind, dest),
+            });
+        }
+    }
+
+    /// The ignored file or directory that a missing link points into
+    fn ignored(&self, target: &str) -> Option<&'a Path> {
+        let target = target.trim_matches('/');
+        self.notebook.ignored().iter().map(|x| x.path.as_path()).find(|path| {
+            let id = self.notebook.note_id(path);
+            if path.is_dir() {
+                target.starts_with(&format!("{}/", id))
+                    || target.contains(&format!("/{}/", id))
+            } else {
+                id == target || id.ends_with(&format!("/{}", target))
+            }
+        })
+    }
+
+    /// Notes without links from other notes. Index notes are expected to
+    /// have none.
+    fn orphans(&self, linked: &HashSet<&str>) -> Vec<&'a Note> {
+        let mut output: Vec<&Note> = self.notebook.notes()
+            .filter(|note| !linked.contains(note.id()))
+            .filter(|note| {
+                let name = note.id().rsplit('/').next().unwrap();
+                !name.eq_ignore_ascii_case("index")
+            })
+            .collect();
+        output.sort_by(|a, b| a.id().cmp(b.id()));
+        output
+    }
+
+    /// Notes sharing a title, ignoring case. Returns the note id and the
+    /// diagnostic.
+    fn duplicate_titles(&self) -> Vec<(&'a str, Diagnostic)> {
+        let mut titles: BTreeMap<String, Vec<&Note>> = BTreeMap::new();
+        for note in self.notebook.notes() {
+            titles.entry(note.title().to_lowercase()).or_default().push(note);
+        }
+
+        let mut output = vec![];
+        for notes in titles.values().filter(|x| x.len() > 1) {
+            for note in notes {
+                let others: Vec<&str> = notes.iter()
+                    .map(|x| x.id())
+                    .filter(|x| *x != note.id())
+                    .collect();
+                output.push((note.id(), Diagnostic {
+                    path: note.path.clone(),
+                    position: None,
+                    message: format!("duplicate title {:?}, also used by {}",
+                                     note.title(), others.join(", ")),
+                }));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    /// The diagnostics of all notes, with paths relative to the basedir
+    fn check(notebook: &Notebook) -> Vec<String> {
+        let prefix = format!("{}/", notebook.basedir().display());
+        Checker::new(notebook).check(notebook.notes()).iter()
+            .map(|x| x.to_string().replace(&prefix, ""))
+            .collect()
+    }
+
+    #[test]
+    fn diagnostics() {
+        let notebook = notebook(&[
+            ("index.md", "---\ntitle: Home\n---\n[[a]] [[b#^blk]] [[b#^none]]\n\
+                          [[missing]] [[todo]] [[draft]]\n\n![img](pic.png)"),
+            ("a.md", "# A\n\n[[index]] and [link](b.html)"),
+            ("b.md", "---\ntitle: a\n---\nParagraph ^blk\n\n[[#^nope]] [[#^own]]\n\n\
+                      Own ^own"),
+            ("work/todo.md", "# Work todo\n[[lonely#Section]]"),
+            ("home/todo.md", "# Home todo\n[[work/todo]]"),
+            ("draft.md", "---\ndraft: true\n---\n[[index]] [[other]]"),
+            ("lonely.md", ""),
+            ("orphan.md", "[[orphan]]"),
+        ]);
+        fs::write(notebook.basedir().join("test.css"), "").unwrap();
+
+        assert_eq!(check(&notebook), vec![
+            "a.md: duplicate title \"A\", also used by b",
+            "b.md: duplicate title \"a\", also used by a",
+            "b.md:6:2: link to missing block ^nope",
+            "draft.md:4:12: unresolved link [other]",
+            "home/todo.md: orphan note, no other note links to it",
+            "index.md:4:19: link [b] to missing block ^none in b",
+            "index.md:5:2: unresolved link [missing]",
+            "index.md:5:14: ambiguous link [todo] matches home/todo, work/todo",
+            "index.md:5:23: link [draft] to unpublished note draft",
+            "index.md:7:1: image pic.png not found",
+            "orphan.md: orphan note, no other note links to it",
+        ]);
+    }
+
+    #[test]
+    fn assets() {
+        let notebook = notebook(&[("index.md", "")]);
+        assert_eq!(check(&notebook), vec!["test.css: asset not found"]);
+    }
+}
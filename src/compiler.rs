@@ -1,13 +1,27 @@
-use pulldown_cmark::{Options, BrokenLink, CowStr, LinkType, html, Event};
+use pulldown_cmark::{Options, BrokenLink, CodeBlockKind, CowStr, LinkType, html, Event, Tag};
 use pulldown_cmark::Parser as CmarkParser;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use crate::{Note, Notebook};
+use crate::{Note, Notebook, Resolved};
+use crate::links::{Link, block_marker, callout_marker, find_blocks, slug};
+use pulldown_cmark::escape::escape_html;
 
 use crate::parsers::Parser;
+use crate::graph::view::local_graph;
+use crate::periodic::PeriodicIndex;
+use crate::live_query::LiveQuery;
+
+/// Maximum number of nested `![[Note]]` embeds
+const MAX_EMBED_DEPTH: usize = 3;
+
+/// Template slots that are only filled in on note pages
+const NOTE_SLOTS: &[&str] = &["{periodic}", "{toc}", "{graph}"];
 
 pub enum Asset {
     Js(PathBuf),
     Css(PathBuf),
+    /// Script written by the build, relative to the outdir
+    BuiltJs(PathBuf),
 }
 
 pub struct NoteCompiler {
@@ -15,13 +29,42 @@ pub struct NoteCompiler {
     pub template: String,
     pub assets: Vec<Asset>,
     pub parsers: Vec<Box<dyn Parser>>,
+    /// Add a `#` link to every heading, pointing at the heading itself
+    pub heading_anchors: bool,
+}
+
+/// A heading of a compiled note
+pub struct Heading {
+    pub level: u32,
+    pub text: String,
+    /// Unique slug, used as `id` attribute
+    pub id: String,
 }
 
 // TODO: Add html fluff around the note, including some css
 // TODO: Add flashcard support
 impl NoteCompiler {
     pub fn to_html(&self, note: &Note, notebook: &Notebook) -> String {
-        let mut contents = note.read();
+        self.render_note(note, notebook).0
+    }
+
+    /// HTML of a note, and its headings
+    fn render_note(&self, note: &Note, notebook: &Notebook)
+        -> (String, Vec<Heading>)
+    {
+        // Relative prefix from this note's html file to the outdir root
+        let root = "../".repeat(
+            note.id().matches('/').count());
+
+        self.render(&note.read(), &root, notebook, &[note.id()])
+    }
+
+    /// Render markdown `contents` for a page that is `root` away from the
+    /// outdir root. `embedding` holds the ids of the note being rendered and
+    /// the notes it is embedded in. Returns the HTML and the headings.
+    fn render(&self, contents: &str, root: &str, notebook: &Notebook,
+              embedding: &[&str]) -> (String, Vec<Heading>) {
+        let mut contents = String::from(contents);
 
         // Run all parsers on this note
         for parser in &self.parsers {
@@ -33,16 +76,32 @@ impl NoteCompiler {
         let func = &mut |link: BrokenLink| {
             match link.link_type {
                 LinkType::Shortcut => {
-                    notebook.get(link.reference).and_then(|note| {
-                        let path = &note.path.strip_prefix(
-                            &notebook.config.basedir
-                        ).unwrap().with_extension("html");
-
-                        Some((
-                            CowStr::from(String::from(path.to_str().unwrap())),
-                            CowStr::from("wikilink"),
-                        ))
-                    })
+                    let wikilink = Link::parse(link.reference, link.span);
+                    // Links to a section or block of this page
+                    if wikilink.target.is_empty() {
+                        let anchor = wikilink.anchor()?;
+                        return Some((CowStr::from(format!("#{}", anchor)),
+                                     CowStr::from("wikilink")));
+                    }
+                    match notebook.resolve(&wikilink.target) {
+                        // Links to notes that are not published become text
+                        Resolved::Found(target)
+                            if !notebook.is_published(target) =>
+                        {
+                            Some((CowStr::from(""), CowStr::from("unpublished")))
+                        },
+                        Resolved::Found(target) => {
+                            let mut url = format!("{}{}.html", root,
+                                                  target.id());
+                            if let Some(anchor) = wikilink.anchor() {
+                                url.push('#');
+                                url.push_str(&anchor);
+                            }
+
+                            Some((CowStr::from(url), CowStr::from("wikilink")))
+                        },
+                        _ => None,
+                    }
                 },
                 _ => None,
             }
@@ -54,30 +113,133 @@ impl NoteCompiler {
             self.parse_options,
             Some(func));
 
+        let mut events = block_anchors(callouts(merge_text(parser.collect())));
+        // Embedded notes would repeat the anchors of their own tasks
+        if embedding.len() == 1 {
+            events = task_anchors(events);
+        }
+        let (events, headings) = heading_ids(events, self.heading_anchors);
+        let events = embeds(events, |reference| {
+            self.embed(reference, root, notebook, embedding)
+        });
+        let events = query_blocks(events, |source| {
+            match LiveQuery::parse(source) {
+                Ok(query) => query.render(notebook, root),
+                Err(err) => format!("<p class=\"query-error\">{}</p>\n",
+                                    escape(&err.to_string())),
+            }
+        });
+
         let mut output = String::new();
-        html::push_html(&mut output, parser);
-        output
+        html::push_html(&mut output, wikilinks(events).into_iter());
+        (output, headings)
+    }
+
+    /// HTML for the embed `![[reference]]`, the rendered target note or
+    /// section with a link back to it. `None` if the target is not a
+    /// published note, so the embed is left as a link.
+    fn embed(&self, reference: &str, root: &str, notebook: &Notebook,
+             embedding: &[&str]) -> Option<String> {
+        let link = Link::parse(reference, 0..0);
+        let target = match notebook.resolve(&link.target) {
+            Resolved::Found(target) if notebook.is_published(target) => target,
+            _ => return None,
+        };
+
+        let mut url = format!("{}{}.html", root, target.id());
+        let mut source = escape(target.title());
+        if let (Some(fragment), Some(anchor)) = (&link.fragment, link.anchor()) {
+            url.push('#');
+            url.push_str(&anchor);
+            if !fragment.starts_with('^') {
+                source.push_str(&format!(" &gt; {}", escape(fragment)));
+            }
+        }
+
+        let contents = target.read();
+        let html = if embedding.contains(&target.id()) {
+            String::from("<p class=\"embed-error\">Not embedded, the note embeds this page</p>\n")
+        } else if embedding.len() > MAX_EMBED_DEPTH {
+            String::from("<p class=\"embed-error\">Not embedded, too many nested embeds</p>\n")
+        } else {
+            let part = match &link.fragment {
+                Some(fragment) if fragment.starts_with('^') =>
+                    find_blocks(&contents, self.parse_options).into_iter()
+                        .find(|(id, _)| *id == fragment[1..])
+                        .map(|(_, range)| &contents[range]),
+                Some(fragment) => section(&contents, fragment, self.parse_options),
+                None => Some(contents.as_str()),
+            };
+            match part {
+                Some(part) => {
+                    let mut embedding = embedding.to_vec();
+                    embedding.push(target.id());
+                    self.render(part, root, notebook, &embedding).0
+                },
+                None => String::from("<p class=\"embed-error\">Section or block not found</p>\n"),
+            }
+        };
+
+        Some(format!("<div class=\"embed\">\n<a class=\"embed-source\" \
+                      href=\"{}\">{}</a>\n{}</div>\n", escape(&url), source, html))
     }
 
     /// Compile Note to a full html buffer, with <html> tags and assets.
-    pub fn to_decorated_html(&self, note: &Note, notebook: &Notebook) -> String {
-        let html = self.to_html(note, notebook);
+    /// `periodic` has the periodic notes of the build, for the navigation
+    /// of daily and weekly notes.
+    pub fn to_decorated_html(&self, note: &Note, notebook: &Notebook,
+                             periodic: &PeriodicIndex) -> String {
+        let (html, headings) = self.render_note(note, notebook);
+        let depth = note.id().matches('/').count();
+
+        let mut slots = vec![];
+        let root = "../".repeat(depth);
+        if let Some(nav) = periodic.navigation(note, &root) {
+            slots.push(("{periodic}", nav));
+        }
+        if note.meta("toc").as_deref() != Some("false") {
+            slots.push(("{toc}", toc(&headings)));
+        }
+        if let Some(hops) = notebook.graph_hops() {
+            slots.push(("{graph}",
+                local_graph(note.id(), hops, notebook.graph_color())));
+        }
 
-        self.template
-            .replace("{title}", &note.title)
-            .replace("{notebook.title}", &notebook.title)
-            .replace("{assets}", &self.assets(&notebook.config.basedir))
-            .replace("{content}", &html)
+        self.to_page(&note.title, &html, depth, &slots, notebook)
+    }
+
+    /// Put `html` in the template. `depth` is the number of directories
+    /// between the page and the root of the outdir. `slots` are the note
+    /// specific template slots, slots that are not given are left empty.
+    pub fn to_page(&self, title: &str, html: &str, depth: usize,
+                   slots: &[(&str, String)], notebook: &Notebook) -> String {
+        let mut page = self.template
+            .replace("{title}", &escape(title))
+            .replace("{notebook.title}", &escape(&notebook.title))
+            .replace("{assets}", &self.assets(&notebook.config.basedir, depth));
+
+        for (slot, value) in slots {
+            page = page.replace(slot, value);
+        }
+        for slot in NOTE_SLOTS {
+            page = page.replace(slot, "");
+        }
+
+        page.replace("{content}", html)
     }
 
     /// Generate string with external asset incluse lines. `basedir` is used to
-    /// find the location of the file
-    fn assets(&self, basedir: &Path) -> String {
+    /// find the location of the file, `depth` is the number of directories
+    /// between the page and the root of the outdir.
+    fn assets(&self, basedir: &Path, depth: usize) -> String {
         self.assets.iter().map(|asset| {
 
             let path = match asset {
                 Asset::Js(path) | Asset::Css(path) => path,
-                _ => panic!("Unknown asset type!"),
+                Asset::BuiltJs(path) => {
+                    return format!("<script src=\"{}{}\"></script>\n",
+                        "../".repeat(depth), path.to_str().unwrap());
+                },
             };
 
             let basedir = basedir.canonicalize().unwrap();
@@ -87,7 +249,7 @@ impl NoteCompiler {
             // TODO: Add support for assets in folders (look for first folder)
             if let Ok(path) = path.canonicalize() {
                 // Find file in parent directories
-                let mut prefix = PathBuf::new();
+                let mut prefix = PathBuf::from("../".repeat(depth));
                 for p in path.ancestors() {
                     if p == basedir {
                         break;
@@ -100,6 +262,7 @@ impl NoteCompiler {
                         prefix.join(path).to_str().unwrap()),
                     Asset::Css(path) => format!("<link rel=\"stylesheet\" href=\"{}\">\n",
                         prefix.join(path).to_str().unwrap()),
+                    Asset::BuiltJs(_) => unreachable!(),
                 }
             } else {
                 // File does not exist
@@ -109,3 +272,657 @@ impl NoteCompiler {
         }).collect::<String>()
     }
 }
+
+/// Escape text for use in HTML
+pub fn escape(text: &str) -> String {
+    let mut output = String::new();
+    escape_html(&mut output, text).unwrap();
+    output
+}
+
+/// Part of markdown `contents` under the heading `name`, up to the next
+/// heading of the same or a higher level. Headings match by their slug, so
+/// case and punctuation do not matter.
+fn section<'a>(contents: &'a str, name: &str, options: Options)
+    -> Option<&'a str>
+{
+    let mut start = None;
+    let mut heading: Option<(u32, usize, String)> = None;
+    for (event, range) in CmarkParser::new_ext(contents, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                if let Some((found, start)) = start {
+                    if level <= found {
+                        return Some(&contents[start..range.start]);
+                    }
+                }
+                heading = Some((level, range.start, String::new()));
+            },
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, title)) = &mut heading {
+                    title.push_str(&text);
+                }
+            },
+            Event::End(Tag::Heading(_)) => {
+                let (level, offset, title) = heading.take()?;
+                if start.is_none() && slug(&title) == slug(name) {
+                    start = Some((level, offset));
+                }
+            },
+            _ => (),
+        }
+    }
+    start.map(|(_, start)| &contents[start..])
+}
+
+/// Give every heading an `id` attribute with its slug. Ids that are already
+/// taken get a number, `notes`, `notes-1`, `notes-2`. With `anchors` a `#`
+/// link to the heading is added to it.
+fn heading_ids(events: Vec<Event>, anchors: bool) -> (Vec<Event>, Vec<Heading>) {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut headings = vec![];
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for event in events {
+        match &event {
+            Event::Start(Tag::Heading(_)) =>
+                current = Some((output.len(), String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = &mut current {
+                    heading.push_str(text);
+                }
+            },
+            Event::End(Tag::Heading(level)) => {
+                let (start, text) = current.take().unwrap();
+                let base = match slug(&text) {
+                    x if x.is_empty() => String::from("section"),
+                    x => x,
+                };
+
+                let mut id = base.clone();
+                while seen.contains_key(&id) {
+                    let count = seen.entry(base.clone()).or_insert(0);
+                    *count += 1;
+                    id = format!("{}-{}", base, count);
+                }
+                seen.insert(id.clone(), 0);
+
+                output[start] = Event::Html(CowStr::from(
+                    format!("<h{} id=\"{}\">", level, escape(&id))));
+                if anchors {
+                    output.push(Event::Html(CowStr::from(format!(
+                        "<a class=\"anchor\" href=\"#{}\">#</a>", escape(&id)))));
+                }
+                headings.push(Heading { level: *level, text, id });
+            },
+            _ => (),
+        }
+        output.push(event);
+    }
+
+    (output, headings)
+}
+
+/// Nested list of links to the `headings`, without the page title. Empty
+/// for pages with fewer than two sections.
+fn toc(headings: &[Heading]) -> String {
+    let headings = match headings.first() {
+        Some(first) if first.level == 1 => &headings[1..],
+        _ => headings,
+    };
+    if headings.len() < 2 {
+        return String::new();
+    }
+
+    let base = headings.iter().map(|x| x.level).min().unwrap();
+    let mut html = String::from("<nav class=\"toc\">\n");
+    let mut depth = 0;
+    for heading in headings {
+        let level = (heading.level - base + 1) as usize;
+        if depth == 0 {
+            html.push_str("<ul>\n<li>");
+            depth = 1;
+        } else if level > depth {
+            while depth < level {
+                html.push_str("\n<ul>\n<li>");
+                depth += 1;
+            }
+        } else {
+            while depth > level {
+                html.push_str("</li>\n</ul>\n");
+                depth -= 1;
+            }
+            html.push_str("</li>\n<li>");
+        }
+        html.push_str(&format!("<a href=\"#{}\">{}</a>", escape(&heading.id),
+                               escape(&heading.text)));
+    }
+    while depth > 0 {
+        html.push_str("</li>\n</ul>\n");
+        depth -= 1;
+    }
+    html.push_str("</nav>\n");
+    html
+}
+
+/// Turn block quotes starting with a `[!type] Title` line into callouts
+///
+/// `> [!warning] Careful` becomes `<aside class="callout callout-warning">`
+/// with the title on top, `Warning` if none is given. With `[!type]-` the
+/// callout is a collapsed `<details>` element, with `[!type]+` it starts
+/// open.
+fn callouts(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    // Closing tag of each open block quote, `None` for normal quotes
+    let mut open: Vec<Option<&str>> = vec![];
+    let mut i = 0;
+
+    while i < events.len() {
+        let marker = match (&events[i], events.get(i + 1), events.get(i + 2)) {
+            (Event::Start(Tag::BlockQuote), Some(Event::Start(Tag::Paragraph)),
+             Some(Event::Text(text))) => callout_marker(text).map(|(kind, fold, end)| (
+                kind.to_lowercase(), fold, String::from(&text[end..]))),
+            _ => None,
+        };
+
+        match (&events[i], marker) {
+            (_, Some((kind, fold, mut title))) => {
+                // The title is the rest of the first line
+                let mut j = i + 3;
+                while let Some(event) = events.get(j) {
+                    match event {
+                        Event::Text(text) | Event::Code(text) =>
+                            title.push_str(text),
+                        Event::SoftBreak | Event::HardBreak => {
+                            j += 1;
+                            break;
+                        },
+                        Event::End(Tag::Paragraph) => break,
+                        _ => (),
+                    }
+                    j += 1;
+                }
+                let title = match title.trim() {
+                    "" => kind[..1].to_uppercase() + &kind[1..],
+                    x => String::from(x),
+                };
+
+                let (kind, title) = (escape(&kind), escape(&title));
+                output.push(Event::Html(CowStr::from(match fold {
+                    "" => format!("<aside class=\"callout callout-{}\">\n\
+                                   <p class=\"callout-title\">{}</p>\n",
+                                  kind, title),
+                    _ => format!("<details class=\"callout callout-{}\"{}>\n\
+                                  <summary class=\"callout-title\">{}</summary>\n",
+                                 kind, if fold == "+" { " open" } else { "" },
+                                 title),
+                })));
+                open.push(Some(if fold.is_empty() { "aside" } else { "details" }));
+
+                // Leave out the paragraph if it only had the title
+                if let Some(Event::End(Tag::Paragraph)) = events.get(j) {
+                    i = j + 1;
+                } else {
+                    output.push(Event::Start(Tag::Paragraph));
+                    i = j;
+                }
+                continue;
+            },
+            (Event::Start(Tag::BlockQuote), None) => open.push(None),
+            (Event::End(Tag::BlockQuote), None) => {
+                if let Some(Some(tag)) = open.pop() {
+                    output.push(Event::Html(CowStr::from(format!("</{}>\n", tag))));
+                    i += 1;
+                    continue;
+                }
+            },
+            _ => (),
+        }
+        output.push(events[i].clone());
+        i += 1;
+    }
+
+    output
+}
+
+/// Replace ```` ```query ```` code blocks with the HTML of their results
+fn query_blocks<F>(events: Vec<Event>, render: F) -> Vec<Event>
+    where F: Fn(&str) -> String
+{
+    let mut output = Vec::with_capacity(events.len());
+    let mut source: Option<String> = None;
+
+    for event in events {
+        match (&mut source, event) {
+            (None, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))))
+                if info.split_whitespace().next() == Some("query") =>
+                source = Some(String::new()),
+            (Some(text), Event::Text(t)) => text.push_str(&t),
+            (Some(_), Event::End(Tag::CodeBlock(_))) => {
+                let html = render(&source.take().unwrap());
+                output.push(Event::Html(CowStr::from(html)));
+            },
+            (_, event) => output.push(event),
+        }
+    }
+    output
+}
+
+/// Put a `task-<number>` anchor after every task checkbox, numbered like
+/// `Task::number`, for the links on the agenda
+fn task_anchors(events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut number = 0;
+    for event in events {
+        let marker = matches!(event, Event::TaskListMarker(_));
+        output.push(event);
+        if marker {
+            number += 1;
+            output.push(Event::Html(CowStr::from(
+                format!("<a class=\"task-anchor\" id=\"task-{}\"></a>", number))));
+        }
+    }
+    output
+}
+
+/// Turn `^block-id` markers at the end of paragraphs and list items into
+/// `id` attributes, so `[[Note#^block-id]]` links can jump to them
+fn block_anchors(events: Vec<Event>) -> Vec<Event> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    let mut open = vec![];
+
+    for event in events {
+        let closes = matches!(event, Event::End(Tag::Paragraph)
+            | Event::End(Tag::Item) | Event::Start(Tag::List(_)));
+        let marker = match output.last() {
+            Some(Event::Text(text)) if closes => block_marker(text)
+                .map(|(offset, id)| (offset, String::from(id))),
+            _ => None,
+        };
+
+        if let (Some((offset, id)), Some(&start)) = (marker, open.last()) {
+            if let Some(Event::Text(text)) = output.pop() {
+                if offset > 0 {
+                    output.push(Event::Text(CowStr::from(text[..offset].to_string())));
+                }
+            }
+            let tag = match output[start] {
+                Event::Start(Tag::Item) => "li",
+                _ => "p",
+            };
+            output[start] = Event::Html(CowStr::from(
+                format!("<{} id=\"^{}\">", tag, escape(&id))));
+        }
+
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Item) =>
+                open.push(output.len()),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Item) => {
+                open.pop();
+            },
+            _ => (),
+        }
+        output.push(event);
+    }
+
+    output
+}
+
+/// Join adjacent text events, so brackets around links are in one event
+fn merge_text(events: Vec<Event>) -> Vec<Event> {
+    let mut output: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        match (output.last_mut(), event) {
+            (Some(Event::Text(last)), Event::Text(text)) =>
+                *last = CowStr::from(format!("{}{}", last, text)),
+            (_, event) => output.push(event),
+        }
+    }
+    output
+}
+
+/// Replace `![[Note]]` and `![[Note#Section]]` embeds in the event stream
+/// with the HTML from `embed`, which gets the link reference
+///
+/// An embed that is alone in a paragraph replaces the paragraph. Embeds
+/// `embed` returns `None` for are left as normal wikilinks, and embeds of
+/// unpublished notes become their text, like links to them.
+fn embeds<'a, F>(events: Vec<Event<'a>>, embed: F) -> Vec<Event<'a>>
+    where F: Fn(&str) -> Option<String>
+{
+    let mut output = Vec::with_capacity(events.len());
+    // Positions of the embeds in `output`
+    let mut embedded = vec![];
+    // Events of the wikilink after a `![`, until its end
+    let mut link: Option<Vec<Event<'a>>> = None;
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        let mut buffer = match link.take() {
+            Some(buffer) => buffer,
+            None => {
+                let is_wikilink = matches!(&event, Event::Start(Tag::Link(_, _, title))
+                    if title.as_ref() == "wikilink" || title.as_ref() == "unpublished");
+                let opened = matches!(output.last(),
+                    Some(Event::Text(t)) if t.ends_with("!["));
+                match is_wikilink && opened {
+                    true => link = Some(vec![event]),
+                    false => output.push(event),
+                }
+                continue;
+            },
+        };
+        let is_end = matches!(event, Event::End(Tag::Link(..)));
+        buffer.push(event);
+        if !is_end {
+            link = Some(buffer);
+            continue;
+        }
+
+        let closed = matches!(events.peek(), Some(Event::Text(t)) if t.starts_with(']'));
+        let published = matches!(&buffer[0], Event::Start(Tag::Link(_, _, title))
+            if title.as_ref() == "wikilink");
+        let html = match closed && published {
+            true => {
+                let text: String = buffer[1..buffer.len() - 1].iter()
+                    .filter_map(|e| match e {
+                        Event::Text(t) | Event::Code(t) => Some(t.as_ref()),
+                        _ => None,
+                    }).collect();
+                embed(&text)
+            },
+            false => None,
+        };
+
+        // Remove the `!`, or the `![` and `]` around the shortcut link
+        let strip = match (&html, closed) {
+            (Some(_), _) => 2,
+            (None, true) if !published => 1,
+            (None, _) => 0,
+        };
+        if strip > 0 {
+            if let Some(Event::Text(t)) = output.pop() {
+                let t = match strip {
+                    1 => format!("{}[", &t[..t.len() - 2]),
+                    _ => String::from(&t[..t.len() - 2]),
+                };
+                if !t.is_empty() {
+                    output.push(Event::Text(CowStr::from(t)));
+                }
+            }
+        }
+        let html = match html {
+            Some(html) => html,
+            None => {
+                output.extend(buffer);
+                continue;
+            },
+        };
+
+        let rest = match events.next() {
+            Some(Event::Text(t)) => String::from(&t[1..]),
+            _ => unreachable!(),
+        };
+        embedded.push(output.len());
+        output.push(Event::Html(CowStr::from(html)));
+        if !rest.is_empty() {
+            output.push(Event::Text(CowStr::from(rest)));
+        }
+    }
+
+    // A link that did not end
+    output.extend(link.into_iter().flatten());
+    unwrap_embeds(output, &embedded)
+}
+
+/// Remove the paragraphs around embeds that are the only thing in them, as
+/// the `<div>` of an embed cannot be inside a `<p>`. `embedded` holds the
+/// positions of the embeds in `events`. The `^id` of a paragraph moves to a
+/// `<div>` around the embeds.
+fn unwrap_embeds<'a>(mut events: Vec<Event<'a>>, embedded: &[usize])
+    -> Vec<Event<'a>>
+{
+    let blank = |event: &Event| match event {
+        Event::Text(t) => t.trim().is_empty(),
+        Event::SoftBreak => true,
+        _ => false,
+    };
+    let mut dropped = vec![false; events.len()];
+
+    for &i in embedded {
+        let start = (0..i).rev()
+            .find(|&j| !(embedded.contains(&j) || blank(&events[j])))
+            .unwrap_or(i);
+        let end = (i + 1..events.len())
+            .find(|&j| !(embedded.contains(&j) || blank(&events[j])))
+            .unwrap_or(i);
+        if dropped[start] || !matches!(events[end], Event::End(Tag::Paragraph)) {
+            continue;
+        }
+
+        let anchor = match &events[start] {
+            Event::Start(Tag::Paragraph) => None,
+            Event::Html(html) if html.starts_with("<p id=") =>
+                Some(html.replacen("<p", "<div", 1)),
+            _ => continue,
+        };
+        for (j, drop) in dropped.iter_mut().enumerate().take(end).skip(start + 1) {
+            *drop = !embedded.contains(&j);
+        }
+        match anchor {
+            Some(anchor) => {
+                events[start] = Event::Html(CowStr::from(anchor));
+                events[end] = Event::Html(CowStr::from("</div>\n"));
+            },
+            None => {
+                dropped[start] = true;
+                dropped[end] = true;
+            },
+        }
+    }
+
+    events.into_iter().zip(dropped)
+        .filter(|(_, dropped)| !dropped)
+        .map(|(event, _)| event)
+        .collect()
+}
+
+/// Clean up resolved wikilinks in the event stream
+///
+/// `[[Note]]` is parsed as the shortcut link `[Note]` surrounded by literal
+/// brackets, which are removed here. The link text of `[[Note|alias]]` and
+/// `[[Note#Section]]` links is replaced with the text to show. Links to
+/// unpublished notes are replaced by their text.
+fn wikilinks(mut events: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut i = 0;
+
+    while i < events.len() {
+        let (is_wikilink, published) = match &events[i] {
+            Event::Start(Tag::Link(_, _, title)) =>
+                (title.as_ref() == "wikilink" || title.as_ref() == "unpublished",
+                 title.as_ref() == "wikilink"),
+            _ => (false, false),
+        };
+        if !is_wikilink {
+            output.push(events[i].clone());
+            i += 1;
+            continue;
+        }
+
+        // Find the end of the link and its text
+        let end = i + events[i..].iter()
+            .position(|e| matches!(e, Event::End(Tag::Link(..))))
+            .unwrap();
+        let text: String = events[i + 1..end].iter().filter_map(|e| match e {
+            Event::Text(t) | Event::Code(t) => Some(t.as_ref()),
+            _ => None,
+        }).collect();
+
+        // Strip surrounding brackets of double bracket links
+        let opened = matches!(output.last(),
+            Some(Event::Text(t)) if t.ends_with('['));
+        let closed = matches!(events.get(end + 1),
+            Some(Event::Text(t)) if t.starts_with(']'));
+        if opened && closed {
+            if let Some(Event::Text(t)) = output.pop() {
+                let t = &t[..t.len() - 1];
+                if !t.is_empty() {
+                    output.push(Event::Text(CowStr::from(t.to_string())));
+                }
+            }
+            if let Event::Text(t) = &events[end + 1] {
+                events[end + 1] = Event::Text(CowStr::from(t[1..].to_string()));
+            }
+        }
+
+        let link = Link::parse(&text, 0..0);
+        if published {
+            output.push(events[i].clone());
+        }
+        if link.alias.is_some() || link.fragment.is_some() {
+            output.push(Event::Text(CowStr::from(link.display())));
+        } else {
+            output.extend(events[i + 1..end].iter().cloned());
+        }
+        if published {
+            output.push(events[end].clone());
+        }
+        i = end + 1;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render<F>(markdown: &str, pass: F) -> String
+        where F: Fn(Vec<Event>) -> Vec<Event>
+    {
+        let events = merge_text(CmarkParser::new_ext(markdown, Options::all()).collect());
+        let mut output = String::new();
+        html::push_html(&mut output, pass(events).into_iter());
+        output
+    }
+
+    fn headings(markdown: &str) -> Vec<Heading> {
+        let events = CmarkParser::new_ext(markdown, Options::all()).collect();
+        heading_ids(events, false).1
+    }
+
+    #[test]
+    fn heading_slugs() {
+        let markdown = "# Notes\n## Notes\n## Notes\n### `code` *here*\n## !!\n";
+        let ids: Vec<String> = headings(markdown).into_iter().map(|x| x.id).collect();
+        assert_eq!(ids, vec!["notes", "notes-1", "notes-2", "code-here", "section"]);
+
+        // A heading with the id of a numbered one gets the next number
+        let markdown = "# A 1\n# A\n# A\n";
+        let ids: Vec<String> = headings(markdown).into_iter().map(|x| x.id).collect();
+        assert_eq!(ids, vec!["a-1", "a", "a-2"]);
+    }
+
+    #[test]
+    fn heading_anchors() {
+        assert_eq!(render("## Two words\n", |x| heading_ids(x, false).0),
+                   "<h2 id=\"two-words\">Two words</h2>\n");
+        assert_eq!(render("## Two words\n", |x| heading_ids(x, true).0),
+                   "<h2 id=\"two-words\">Two words\
+                    <a class=\"anchor\" href=\"#two-words\">#</a></h2>\n");
+    }
+
+    #[test]
+    fn table_of_contents() {
+        // Pages with fewer than two sections have none
+        assert_eq!(toc(&headings("# Title\n## One\n")), "");
+
+        assert_eq!(toc(&headings("# Title\n## One\n### One A\n## Two\n")), concat!(
+            "<nav class=\"toc\">\n",
+            "<ul>\n<li><a href=\"#one\">One</a>\n",
+            "<ul>\n<li><a href=\"#one-a\">One A</a></li>\n</ul>\n",
+            "</li>\n<li><a href=\"#two\">Two</a></li>\n</ul>\n",
+            "</nav>\n"));
+    }
+
+    #[test]
+    fn callout() {
+        assert_eq!(render("> [!WARNING] Careful *now*\n> Body text\n", callouts), concat!(
+            "<aside class=\"callout callout-warning\">\n",
+            "<p class=\"callout-title\">Careful now</p>\n",
+            "<p>Body text</p>\n",
+            "</aside>\n"));
+        assert_eq!(render("> [!note]\n>\n> Body\n", callouts), concat!(
+            "<aside class=\"callout callout-note\">\n",
+            "<p class=\"callout-title\">Note</p>\n",
+            "<p>Body</p>\n",
+            "</aside>\n"));
+    }
+
+    #[test]
+    fn foldable_callout() {
+        assert_eq!(render("> [!tip]- Hidden\n> Body\n", callouts), concat!(
+            "<details class=\"callout callout-tip\">\n",
+            "<summary class=\"callout-title\">Hidden</summary>\n",
+            "<p>Body</p>\n",
+            "</details>\n"));
+        assert!(render("> [!tip]+\n> Body\n", callouts)
+            .starts_with("<details class=\"callout callout-tip\" open>\n"));
+    }
+
+    #[test]
+    fn nested_callouts() {
+        let html = render("> [!info]\n> > quoted\n>\n> > [!bug] Inner\n> > text\n", callouts);
+        assert_eq!(html, concat!(
+            "<aside class=\"callout callout-info\">\n",
+            "<p class=\"callout-title\">Info</p>\n",
+            "<blockquote>\n<p>quoted</p>\n</blockquote>\n",
+            "<aside class=\"callout callout-bug\">\n",
+            "<p class=\"callout-title\">Inner</p>\n",
+            "<p>text</p>\n",
+            "</aside>\n",
+            "</aside>\n"));
+
+        // Only a marker at the start of the quote makes a callout
+        assert_eq!(render("> Text [!note]\n", callouts),
+                   "<blockquote>\n<p>Text [!note]</p>\n</blockquote>\n");
+    }
+
+    /// Render with `embeds` and `wikilinks`, embedding `[[pub]]` and
+    /// treating `[[hidden]]` as unpublished
+    fn render_embeds(markdown: &str) -> String {
+        let func = &mut |link: BrokenLink| match link.reference {
+            "pub" => Some((CowStr::from("pub.html"), CowStr::from("wikilink"))),
+            "hidden" => Some((CowStr::from(""), CowStr::from("unpublished"))),
+            _ => None,
+        };
+        let parser = CmarkParser::new_with_broken_link_callback(
+            markdown, Options::all(), Some(func));
+        let events = embeds(block_anchors(merge_text(parser.collect())),
+                            |reference| Some(format!("<div>{}</div>", reference)));
+        let mut output = String::new();
+        html::push_html(&mut output, wikilinks(events).into_iter());
+        output
+    }
+
+    #[test]
+    fn embed() {
+        // Alone in a paragraph the embed replaces it
+        assert_eq!(render_embeds("![[pub]]\n"), "<div>pub</div>");
+        assert_eq!(render_embeds("Text\n\n![[pub]]\n![[pub]]  \n"),
+                   "<p>Text</p>\n<div>pub</div><div>pub</div>");
+        assert_eq!(render_embeds("![[pub]] ^id\n"),
+                   "<div id=\"^id\"><div>pub</div></div>\n");
+        assert_eq!(render_embeds("> ![[pub]]\n"),
+                   "<blockquote>\n<div>pub</div></blockquote>\n");
+        assert_eq!(render_embeds("![[pub]]\n![[missing]]\n"),
+                   "<p><div>pub</div>\n![[missing]]</p>\n");
+        assert_eq!(render_embeds("a ![[pub]]![[pub]] b\n"),
+                   "<p>a <div>pub</div><div>pub</div> b</p>\n");
+        assert_eq!(render_embeds("![[hidden]] and [[hidden]]\n"),
+                   "<p>hidden and hidden</p>\n");
+        assert_eq!(render_embeds("![[missing]]\n"), "<p>![[missing]]</p>\n");
+    }
+}
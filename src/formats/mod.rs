@@ -0,0 +1,36 @@
+mod org;
+pub use org::OrgFormat;
+
+use std::rc::Rc;
+
+/// An input markup language for notes
+///
+/// Every format is converted to markdown, including a YAML front matter block
+/// for its metadata, so all notes can go through the same `NoteCompiler`,
+/// wikilink and metadata handling.
+pub trait InputFormat {
+    fn name(&self) -> &str;
+    fn to_markdown(&self, content: &str) -> String;
+}
+
+/// Markdown notes, which need no conversion
+pub struct MarkdownFormat;
+
+impl InputFormat for MarkdownFormat {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn to_markdown(&self, content: &str) -> String {
+        String::from(content)
+    }
+}
+
+/// Get the builtin format for a file extension. Unknown extensions are
+/// treated as markdown.
+pub fn for_extension(extension: &str) -> Rc<dyn InputFormat> {
+    match extension {
+        "org" => Rc::new(OrgFormat),
+        _ => Rc::new(MarkdownFormat),
+    }
+}
@@ -0,0 +1,267 @@
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+use crate::formats::InputFormat;
+
+/// Org-mode notes
+///
+/// Supports the commonly used subset of Org: `#+KEY:` keywords (as metadata),
+/// headlines, source, example and quote blocks, lists, tables, links and
+/// inline markup.
+pub struct OrgFormat;
+
+impl InputFormat for OrgFormat {
+    fn name(&self) -> &str {
+        "org"
+    }
+
+    fn to_markdown(&self, content: &str) -> String {
+        let Patterns { keyword, headline, list, table_rule, inline } = patterns();
+
+        let mut metadata = vec![];
+        let mut body = vec![];
+        let mut block: Option<String> = None;
+        let mut in_header = true;
+
+        for line in content.lines() {
+            // Inside a block
+            if let Some(kind) = &block {
+                let upper = line.trim().to_uppercase();
+                if upper == format!("#+END_{}", kind) {
+                    match kind.as_str() {
+                        // End the quote, so the next line is not a lazy
+                        // continuation
+                        "QUOTE" => body.push(String::new()),
+                        _ => body.push(String::from("```")),
+                    }
+                    block = None;
+                } else if kind == "QUOTE" {
+                    body.push(format!("> {}", inline.convert(line.trim())));
+                } else {
+                    body.push(String::from(line));
+                }
+                continue;
+            }
+
+            let trimmed = line.trim();
+            if let Some(rest) = trimmed.get(..8)
+                .filter(|x| x.eq_ignore_ascii_case("#+BEGIN_"))
+                .map(|_| &trimmed[8..])
+            {
+                let mut words = rest.split_whitespace();
+                let kind = words.next().unwrap_or("").to_uppercase();
+                if kind != "QUOTE" {
+                    let lang = if kind == "SRC" { words.next().unwrap_or("") }
+                               else { "" };
+                    body.push(format!("```{}", lang));
+                }
+                block = Some(kind);
+                continue;
+            }
+
+            if let Some(cap) = keyword.captures(line) {
+                // Keywords before the content are the note's metadata
+                if in_header {
+                    let key = cap[1].to_lowercase();
+                    match key.as_str() {
+                        "filetags" => metadata.push(format!("tags: [{}]",
+                            cap[2].split(':')
+                                .filter(|x| !x.is_empty())
+                                .collect::<Vec<_>>().join(", "))),
+                        _ => metadata.push(format!("{}: {}", key, &cap[2])),
+                    }
+                }
+                continue;
+            }
+            if !trimmed.is_empty() {
+                in_header = false;
+            }
+
+            // Comments
+            if trimmed == "#" || trimmed.starts_with("# ") {
+                continue;
+            }
+
+            if let Some(cap) = headline.captures(line) {
+                body.push(format!("{} {}", "#".repeat(cap[1].len().min(6)),
+                                  inline.convert(&cap[2])));
+            } else if let Some(cap) = list.captures(line) {
+                let marker = match cap.get(2) {
+                    Some(n) => format!("{}.", n.as_str()),
+                    None => String::from("-"),
+                };
+                body.push(format!("{}{} {}", &cap[1], marker,
+                                  inline.convert(&cap[3])));
+            } else if table_rule.is_match(line) {
+                body.push(line.replace('+', "|"));
+            } else {
+                body.push(inline.convert(line));
+            }
+        }
+
+        let mut output = String::new();
+        if !metadata.is_empty() {
+            output.push_str("---\n");
+            for line in metadata {
+                output.push_str(&line);
+                output.push('\n');
+            }
+            output.push_str("---\n");
+        }
+        for line in body {
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Regexes for the lines of a note
+struct Patterns {
+    keyword: Regex,
+    headline: Regex,
+    list: Regex,
+    table_rule: Regex,
+    inline: Inline,
+}
+
+/// The patterns, compiled on first use
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        keyword: Regex::new(r"^#\+(\w+):\s*(.*)$").unwrap(),
+        headline: Regex::new(r"^(\*+)\s+(.*)$").unwrap(),
+        list: Regex::new(r"^(\s*)(?:[-+]|(\d+)[.)])\s+(.*)$").unwrap(),
+        table_rule: Regex::new(r"^\s*\|[-+]+\|?\s*$").unwrap(),
+        inline: Inline::new(),
+    })
+}
+
+/// Converter for inline Org markup
+struct Inline {
+    link: Regex,
+    emphasis: Vec<(Regex, &'static str)>,
+}
+
+impl Inline {
+    fn new() -> Self {
+        // Markup must be surrounded by whitespace or punctuation
+        let markup = |c: &str| Regex::new(&format!(
+            r#"(^|[\s(\-'"{{])\{c}([^\s{c}](?:[^{c}]*[^\s{c}])?)\{c}($|[\s\-.,;:!?'")}}\[])"#,
+            c = c)).unwrap();
+
+        Self {
+            link: Regex::new(r"\[\[([^\]]+)\](?:\[([^\]]+)\])?\]").unwrap(),
+            emphasis: vec![
+                (markup("*"), "$1**$2**$3"),
+                (markup("/"), "$1*$2*$3"),
+                (markup("="), "$1`$2`$3"),
+                (markup("~"), "$1`$2`$3"),
+                (markup("+"), "$1~~$2~~$3"),
+            ],
+        }
+    }
+
+    /// Convert links and emphasis on a single line
+    fn convert(&self, line: &str) -> String {
+        let mut output = String::new();
+        let mut last = 0;
+
+        // Only convert emphasis outside of links, so urls are left intact
+        for cap in self.link.captures_iter(line) {
+            let m = cap.get(0).unwrap();
+            output.push_str(&self.emphasis(&line[last..m.start()]));
+            output.push_str(&Self::link(&cap));
+            last = m.end();
+        }
+        output.push_str(&self.emphasis(&line[last..]));
+        output
+    }
+
+    fn emphasis(&self, text: &str) -> String {
+        self.emphasis.iter().fold(String::from(text), |text, (re, rep)| {
+            re.replace_all(&text, *rep).into_owned()
+        })
+    }
+
+    /// `[[target][description]]` to a markdown link, or a wikilink for links
+    /// to other notes
+    fn link(cap: &Captures) -> String {
+        let target = &cap[1];
+        let desc = cap.get(2).map(|x| x.as_str());
+
+        if target.contains("://") || target.starts_with("mailto:") {
+            return format!("[{}]({})", desc.unwrap_or(target), target);
+        }
+
+        // Links to other org files are links to notes
+        let (file, section) = match target.split_once("::") {
+            Some((file, section)) => (file, Some(section.trim_start_matches('*'))),
+            None => (target, None),
+        };
+        let file = file.strip_prefix("file:").unwrap_or(file);
+        let file = file.strip_suffix(".org").unwrap_or(file);
+        let target = match section {
+            Some(section) => format!("{}#{}", file, section),
+            None => String::from(file),
+        };
+        match desc {
+            Some(desc) => format!("[[{}|{}]]", target, desc),
+            None => format!("[[{}]]", target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(org: &str) -> String {
+        OrgFormat.to_markdown(org)
+    }
+
+    #[test]
+    fn keywords() {
+        let org = "#+TITLE: A note\n#+FILETAGS: :work:urgent:\n\nText\n#+KEY: ignored\n";
+        assert_eq!(convert(org), "---\ntitle: A note\ntags: [work, urgent]\n---\n\nText\n");
+    }
+
+    #[test]
+    fn headlines_and_lists() {
+        let org = "* One\n*** Three\n- item\n  + nested\n2) second\n# comment\n";
+        assert_eq!(convert(org), "# One\n### Three\n- item\n  - nested\n2. second\n");
+    }
+
+    #[test]
+    fn blocks() {
+        let org = "#+begin_src rust\nlet x = *y*;\n#+end_src\n\
+                   #+BEGIN_QUOTE\nSaid /this/\n#+END_QUOTE\n\
+                   #+BEGIN_EXAMPLE\n* not a headline\n#+END_EXAMPLE\n";
+        assert_eq!(convert(org), "```rust\nlet x = *y*;\n```\n\
+                                  > Said *this*\n\n\
+                                  ```\n* not a headline\n```\n");
+    }
+
+    #[test]
+    fn tables() {
+        let org = "| a | b |\n|---+---|\n| 1 | 2 |\n";
+        assert_eq!(convert(org), "| a | b |\n|---|---|\n| 1 | 2 |\n");
+    }
+
+    #[test]
+    fn inline() {
+        assert_eq!(convert("*bold* /italic/ =code= ~code~ +strike+\n"),
+                   "**bold** *italic* `code` `code` ~~strike~~\n");
+        // Markup needs whitespace or punctuation around it
+        assert_eq!(convert("a*b*c 2*3 = 6\n"), "a*b*c 2*3 = 6\n");
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(convert("[[https://example.com/a_/b/][Site]]\n"),
+                   "[Site](https://example.com/a_/b/)\n");
+        assert_eq!(convert("[[file:other.org][Other]] [[Note]]\n"),
+                   "[[other|Other]] [[Note]]\n");
+        assert_eq!(convert("[[file:other.org::*Section]]\n"), "[[other#Section]]\n");
+    }
+}
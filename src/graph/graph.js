@@ -0,0 +1,207 @@
+// Graph view for notebooks compiled by `notes`. Draws a force-directed graph
+// in every `.notes-graph` element. `NOTES_GRAPH` is prepended by the build.
+//
+// Element attributes:
+//   data-note   Only show the neighbourhood of this note id
+//   data-hops   Size of the neighbourhood, defaults to 1
+//   data-color  Colour nodes by "folder" (default) or "tag"
+(function() {
+    "use strict";
+
+    var graph = NOTES_GRAPH;
+    var root = document.currentScript.src.replace(/[^\/]*$/, "");
+
+    // Ids of the nodes within `hops` links of `id`, ignoring link direction
+    function neighbourhood(id, hops) {
+        var found = {};
+        found[id] = true;
+        var frontier = [id];
+        for (var i = 0; i < hops; i++) {
+            var next = [];
+            graph.edges.forEach(function(e) {
+                [[e.source, e.target], [e.target, e.source]].forEach(function(p) {
+                    if (frontier.indexOf(p[0]) >= 0 && !found[p[1]]) {
+                        found[p[1]] = true;
+                        next.push(p[1]);
+                    }
+                });
+            });
+            frontier = next;
+        }
+        return found;
+    }
+
+    function group(node, color) {
+        if (color === "tag") {
+            return node.tags.length > 0 ? node.tags[0].split("/")[0] : "";
+        }
+        var i = node.id.lastIndexOf("/");
+        return i >= 0 ? node.id.slice(0, i) : "";
+    }
+
+    function hue(text) {
+        var hash = 0;
+        for (var i = 0; i < text.length; i++) {
+            hash = (hash * 31 + text.charCodeAt(i)) % 360;
+        }
+        return hash;
+    }
+
+    function draw(element) {
+        var color = element.dataset.color || "folder";
+        var keep = null;
+        if (element.dataset.note) {
+            keep = neighbourhood(element.dataset.note,
+                                 parseInt(element.dataset.hops || "1", 10));
+        }
+
+        var nodes = graph.nodes.filter(function(n) {
+            return keep === null || keep[n.id];
+        }).map(function(n, i) {
+            var angle = i * 2.4;
+            return {
+                data: n,
+                x: Math.cos(angle) * 10 * Math.sqrt(i + 1),
+                y: Math.sin(angle) * 10 * Math.sqrt(i + 1),
+                vx: 0, vy: 0,
+                r: 4 + 2 * Math.sqrt(n.backlinks),
+                current: n.id === element.dataset.note,
+            };
+        });
+        var byId = {};
+        nodes.forEach(function(n) { byId[n.data.id] = n; });
+        var edges = graph.edges.filter(function(e) {
+            return byId[e.source] && byId[e.target];
+        });
+
+        var canvas = document.createElement("canvas");
+        var width = element.clientWidth || 600;
+        var height = element.dataset.note ? 250 : 600;
+        var scale = window.devicePixelRatio || 1;
+        canvas.width = width * scale;
+        canvas.height = height * scale;
+        canvas.style.width = width + "px";
+        canvas.style.height = height + "px";
+        element.appendChild(canvas);
+        var ctx = canvas.getContext("2d");
+
+        var hover = null;
+        var ticks = 0;
+
+        function step() {
+            // Repulsion between all nodes
+            for (var i = 0; i < nodes.length; i++) {
+                for (var j = i + 1; j < nodes.length; j++) {
+                    var a = nodes[i], b = nodes[j];
+                    var dx = b.x - a.x, dy = b.y - a.y;
+                    var d2 = Math.max(dx * dx + dy * dy, 1);
+                    var f = 400 / d2;
+                    a.vx -= dx * f; a.vy -= dy * f;
+                    b.vx += dx * f; b.vy += dy * f;
+                }
+            }
+            // Springs along the links
+            edges.forEach(function(e) {
+                var a = byId[e.source], b = byId[e.target];
+                var dx = b.x - a.x, dy = b.y - a.y;
+                var d = Math.sqrt(dx * dx + dy * dy) || 1;
+                var f = (d - 60) * 0.01;
+                a.vx += dx / d * f; a.vy += dy / d * f;
+                b.vx -= dx / d * f; b.vy -= dy / d * f;
+            });
+            // Pull towards the centre and damp
+            nodes.forEach(function(n) {
+                n.vx = (n.vx - n.x * 0.005) * 0.8;
+                n.vy = (n.vy - n.y * 0.005) * 0.8;
+                n.x += n.vx;
+                n.y += n.vy;
+            });
+        }
+
+        function render() {
+            ctx.setTransform(scale, 0, 0, scale, width / 2 * scale,
+                             height / 2 * scale);
+            ctx.clearRect(-width / 2, -height / 2, width, height);
+
+            ctx.strokeStyle = "#ccc";
+            edges.forEach(function(e) {
+                var a = byId[e.source], b = byId[e.target];
+                ctx.lineWidth = Math.min(e.count, 4) * 0.5;
+                ctx.beginPath();
+                ctx.moveTo(a.x, a.y);
+                ctx.lineTo(b.x, b.y);
+                ctx.stroke();
+            });
+
+            ctx.font = "11px sans-serif";
+            ctx.textAlign = "center";
+            nodes.forEach(function(n) {
+                ctx.fillStyle = n.data.resolved
+                    ? "hsl(" + hue(group(n.data, color)) + ", 55%, 55%)"
+                    : "#bbb";
+                ctx.beginPath();
+                ctx.arc(n.x, n.y, n.r, 0, 2 * Math.PI);
+                ctx.fill();
+                if (n.current || n === hover) {
+                    ctx.strokeStyle = "#1a1a1a";
+                    ctx.lineWidth = 1.5;
+                    ctx.stroke();
+                }
+                if (n === hover || n.current || nodes.length < 30
+                        || n.data.backlinks > 2) {
+                    ctx.fillStyle = "#1a1a1a";
+                    ctx.fillText(n.data.title, n.x, n.y - n.r - 3);
+                }
+            });
+        }
+
+        function tick() {
+            step();
+            render();
+            if (++ticks < 300) {
+                window.requestAnimationFrame(tick);
+            }
+        }
+        tick();
+
+        function nodeAt(event) {
+            var rect = canvas.getBoundingClientRect();
+            var x = event.clientX - rect.left - width / 2;
+            var y = event.clientY - rect.top - height / 2;
+            return nodes.find(function(n) {
+                var dx = n.x - x, dy = n.y - y;
+                return dx * dx + dy * dy <= (n.r + 2) * (n.r + 2);
+            }) || null;
+        }
+
+        canvas.addEventListener("mousemove", function(event) {
+            var node = nodeAt(event);
+            if (node !== hover) {
+                hover = node;
+                canvas.style.cursor = node && node.data.resolved ? "pointer" : "";
+                render();
+            }
+        });
+        canvas.addEventListener("click", function(event) {
+            var node = nodeAt(event);
+            if (node && node.data.resolved) {
+                window.location.href = root + node.data.id + ".html";
+            }
+        });
+    }
+
+    document.addEventListener("DOMContentLoaded", function() {
+        document.querySelectorAll(".notes-graph").forEach(draw);
+
+        var select = document.getElementById("notes-graph-color");
+        if (select) {
+            select.addEventListener("change", function() {
+                document.querySelectorAll(".notes-graph").forEach(function(e) {
+                    e.dataset.color = select.value;
+                    e.innerHTML = "";
+                    draw(e);
+                });
+            });
+        }
+    });
+})();
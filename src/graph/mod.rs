@@ -0,0 +1,207 @@
+pub mod view;
+
+use std::collections::BTreeMap;
+
+use crate::{Notebook, Resolved};
+use crate::compiler::escape;
+use crate::json;
+use crate::query::Notes;
+
+/// A note, or an unresolved link target, in the link graph
+pub struct Node {
+    pub title: String,
+    pub tags: Vec<String>,
+    /// Path of the note file, relative to the basedir. Empty for unresolved
+    /// link targets.
+    pub path: String,
+    pub resolved: bool,
+}
+
+/// Graph of the wikilinks between notes
+///
+/// Nodes are keyed by note id, unresolved link targets by their link text.
+/// Edges are keyed by (source, target), with the number of links as value.
+pub struct Graph {
+    pub nodes: BTreeMap<String, Node>,
+    pub edges: BTreeMap<(String, String), usize>,
+}
+
+impl Graph {
+    /// Build the graph of the selected `notes`. Links to notes that are not
+    /// selected are left out. Links that do not resolve to a single note are
+    /// only included with `unresolved`.
+    pub fn build(notebook: &Notebook, notes: Notes, unresolved: bool) -> Self {
+        let notes: Vec<_> = notes.collect();
+
+        let mut nodes = BTreeMap::new();
+        for note in &notes {
+            nodes.insert(String::from(note.id()), Node {
+                title: String::from(note.title()),
+                tags: note.tags().to_vec(),
+                path: note.path.strip_prefix(notebook.basedir())
+                    .unwrap_or(&note.path).to_string_lossy().to_string(),
+                resolved: true,
+            });
+        }
+
+        let mut edges = BTreeMap::new();
+        let mut missing = vec![];
+        for note in &notes {
+            for link in notebook.links(note) {
+                let target = match notebook.resolve(&link.target) {
+                    Resolved::Found(target) if nodes.contains_key(target.id()) =>
+                        String::from(target.id()),
+                    Resolved::Found(_) => continue,
+                    _ if unresolved && !link.target.is_empty() => {
+                        missing.push(link.target.clone());
+                        link.target
+                    },
+                    _ => continue,
+                };
+                *edges.entry((String::from(note.id()), target)).or_insert(0) += 1;
+            }
+        }
+
+        for target in missing {
+            nodes.entry(target.clone()).or_insert(Node {
+                title: target,
+                tags: vec![],
+                path: String::new(),
+                resolved: false,
+            });
+        }
+
+        Self { nodes, edges }
+    }
+
+    /// Number of links pointing to each node
+    pub fn backlinks(&self) -> BTreeMap<&str, usize> {
+        let mut output: BTreeMap<&str, usize> =
+            self.nodes.keys().map(|id| (id.as_str(), 0)).collect();
+        for ((_, target), count) in &self.edges {
+            *output.entry(target.as_str()).or_insert(0) += count;
+        }
+        output
+    }
+
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"",
+            s.replace('\\', "\\\\").replace('"', "\\\""));
+
+        let mut output = String::from("digraph notes {\n");
+        for (id, node) in &self.nodes {
+            let style = if node.resolved { "" } else { ", style=dashed" };
+            output.push_str(&format!("    {} [label={}{}];\n",
+                quote(id), quote(&node.title), style));
+        }
+        for ((source, target), count) in &self.edges {
+            output.push_str(&format!("    {} -> {} [weight={}, label={}];\n",
+                quote(source), quote(target), count, count));
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let backlinks = self.backlinks();
+        let nodes: Vec<String> = self.nodes.iter().map(|(id, node)| {
+            format!("{{\"id\":{},\"title\":{},\"tags\":{},\"path\":{},\
+                      \"resolved\":{},\"backlinks\":{}}}",
+                json::string(id), json::string(&node.title),
+                json::string_array(&node.tags), json::string(&node.path),
+                node.resolved, backlinks[id.as_str()])
+        }).collect();
+        let edges: Vec<String> = self.edges.iter()
+            .map(|((source, target), count)| {
+                format!("{{\"source\":{},\"target\":{},\"count\":{}}}",
+                    json::string(source), json::string(target), count)
+            }).collect();
+
+        format!("{{\"nodes\":[{}],\"edges\":[{}]}}\n",
+                nodes.join(","), edges.join(","))
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut output = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"title\" for=\"node\" attr.name=\"title\" attr.type=\"string\"/>\n",
+            "  <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n",
+            "  <key id=\"path\" for=\"node\" attr.name=\"path\" attr.type=\"string\"/>\n",
+            "  <key id=\"resolved\" for=\"node\" attr.name=\"resolved\" attr.type=\"boolean\"/>\n",
+            "  <key id=\"count\" for=\"edge\" attr.name=\"count\" attr.type=\"int\"/>\n",
+            "  <graph id=\"notes\" edgedefault=\"directed\">\n"));
+
+        for (id, node) in &self.nodes {
+            output.push_str(&format!(concat!(
+                "    <node id=\"{}\">\n",
+                "      <data key=\"title\">{}</data>\n",
+                "      <data key=\"tags\">{}</data>\n",
+                "      <data key=\"path\">{}</data>\n",
+                "      <data key=\"resolved\">{}</data>\n",
+                "    </node>\n"),
+                escape(id), escape(&node.title), escape(&node.tags.join(",")),
+                escape(&node.path), node.resolved));
+        }
+        for ((source, target), count) in &self.edges {
+            output.push_str(&format!(concat!(
+                "    <edge source=\"{}\" target=\"{}\">\n",
+                "      <data key=\"count\">{}</data>\n",
+                "    </edge>\n"),
+                escape(source), escape(target), count));
+        }
+
+        output.push_str("  </graph>\n</graphml>\n");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    fn sample() -> Notebook {
+        notebook(&[
+            ("a.md", "---\ntitle: Say \"hi\" \\ bye\ntags: [x]\n---\n\
+                      [[b]], [[b#Part]], [[nowhere]] and [[draft]]"),
+            ("dir/b.md", "[[a]]"),
+            ("draft.md", "---\ndraft: true\n---\n[[a]]"),
+        ])
+    }
+
+    #[test]
+    fn build() {
+        let notebook = sample();
+
+        let graph = Graph::build(&notebook, notebook.notes().published(), false);
+        assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["a", "dir/b"]);
+        assert_eq!(graph.nodes["dir/b"].path, "dir/b.md");
+        assert_eq!(graph.edges.iter().collect::<Vec<_>>(), vec![
+            (&(String::from("a"), String::from("dir/b")), &2),
+            (&(String::from("dir/b"), String::from("a")), &1),
+        ]);
+
+        let graph = Graph::build(&notebook, notebook.notes().published(), true);
+        assert!(!graph.nodes["nowhere"].resolved);
+        assert_eq!(graph.backlinks(), [("a", 1), ("dir/b", 2), ("nowhere", 1)]
+                   .iter().copied().collect());
+    }
+
+    #[test]
+    fn escaping() {
+        let notebook = sample();
+        let graph = Graph::build(&notebook, notebook.notes().with_tag("x"), false);
+
+        assert_eq!(graph.to_json(), concat!(
+            "{\"nodes\":[{\"id\":\"a\",\"title\":\"Say \\\"hi\\\" \\\\ bye\",",
+            "\"tags\":[\"x\"],\"path\":\"a.md\",\"resolved\":true,\"backlinks\":0}],",
+            "\"edges\":[]}\n"));
+        assert_eq!(graph.to_dot(), concat!(
+            "digraph notes {\n",
+            "    \"a\" [label=\"Say \\\"hi\\\" \\\\ bye\"];\n",
+            "}\n"));
+        assert!(graph.to_graphml()
+                .contains("<data key=\"title\">Say &quot;hi&quot; \\ bye</data>"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+use std::fs;
+
+use crate::Notebook;
+use crate::compiler::escape;
+use crate::graph::Graph;
+
+/// File name of the graph page, in the root of the outdir
+pub const GRAPH_PAGE: &str = "graph.html";
+/// File name of the graph script with the graph data, in the root of the
+/// outdir
+pub const GRAPH_SCRIPT: &str = "graph.js";
+
+const SCRIPT: &str = include_str!("graph.js");
+
+/// Writes the graph page, and the script that draws the full graph and the
+/// local graphs of notes
+pub struct GraphView<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> GraphView<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    pub fn write_all(&self) -> io::Result<()> {
+        let outdir = self.notebook.outdir();
+        fs::create_dir_all(&outdir)?;
+
+        // The data is part of the script, so it also works from file:// urls
+        let graph = Graph::build(self.notebook,
+                                 self.notebook.notes().published(), false);
+        let mut file = fs::File::create(outdir.join(GRAPH_SCRIPT))?;
+        write!(file, "var NOTES_GRAPH = {};\n{}", graph.to_json().trim_end(),
+               SCRIPT)?;
+
+        let outfile = outdir.join(GRAPH_PAGE);
+        println!("Writing to {}", outfile.display());
+        fs::File::create(&outfile)?.write_all(self.render().as_bytes())
+    }
+
+    fn render(&self) -> String {
+        let color = self.notebook.graph_color();
+        let options: String = ["folder", "tag"].iter().map(|x| {
+            let selected = if *x == color { " selected" } else { "" };
+            format!("<option value=\"{}\"{}>{}</option>", x, selected, x)
+        }).collect();
+
+        let html = format!(concat!(
+            "<h1>Graph</h1>\n",
+            "<p>Colour by <select id=\"notes-graph-color\">{}</select></p>\n",
+            "<div class=\"notes-graph\" data-color=\"{}\"></div>\n"),
+            options, escape(color));
+
+        self.notebook.compiler().to_page("Graph", &html, 0, &[], self.notebook)
+    }
+}
+
+/// The `{graph}` template slot of a note, showing the notes within `hops`
+/// links
+pub fn local_graph(id: &str, hops: usize, color: &str) -> String {
+    format!("<div class=\"notes-graph\" data-note=\"{}\" data-hops=\"{}\" \
+             data-color=\"{}\"></div>", escape(id), hops, escape(color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn write_all() {
+        let notebook = notebook(&[
+            ("a.md", "---\ntitle: </script>\n---\n[[b]]"),
+            ("b.md", ""),
+            ("draft.md", "---\ndraft: true\n---\n[[a]]"),
+        ]);
+        GraphView::new(&notebook).write_all().unwrap();
+
+        let outdir = notebook.outdir();
+        let script = fs::read_to_string(outdir.join(GRAPH_SCRIPT)).unwrap();
+        assert!(script.starts_with("var NOTES_GRAPH = {\"nodes\":[{\"id\":\"a\",\
+                                    \"title\":\"\\u003c/script>\""));
+        assert!(!script.contains("draft"));
+        assert!(script.ends_with(SCRIPT));
+        assert!(fs::read_to_string(outdir.join(GRAPH_PAGE)).unwrap()
+                .contains("<div class=\"notes-graph\" data-color=\"folder\"></div>"));
+    }
+
+    #[test]
+    fn local() {
+        assert_eq!(local_graph("a\"b", 2, "tag"),
+                   "<div class=\"notes-graph\" data-note=\"a&quot;b\" data-hops=\"2\" \
+                    data-color=\"tag\"></div>");
+    }
+}
@@ -0,0 +1,303 @@
+//! iCalendar (RFC 5545) export of tasks and events
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::SystemTime;
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::tasks::{Priority, Task};
+
+/// File name of the calendar feed, in the root of the outdir
+pub const ICAL_FILE: &str = "calendar.ics";
+
+/// Date or date and time of an event, as found in the front matter
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum When {
+    Day(NaiveDate),
+    Time(NaiveDateTime),
+}
+
+impl When {
+    /// `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or `YYYY-MM-DDTHH:MM[:SS]`
+    fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+            return Some(When::Day(date));
+        }
+        ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .map(When::Time)
+    }
+
+    /// Property with the date, or the floating local time
+    fn property(&self, name: &str) -> String {
+        match self {
+            When::Day(date) => format!("{};VALUE=DATE:{}", name, date.format("%Y%m%d")),
+            When::Time(time) => format!("{}:{}", name, time.format("%Y%m%dT%H%M%S")),
+        }
+    }
+
+    /// Default end: the next day for all-day events, one hour later otherwise
+    fn default_end(&self) -> Self {
+        match *self {
+            When::Day(date) => When::Day(date + Duration::days(1)),
+            When::Time(time) => When::Time(time + Duration::hours(1)),
+        }
+    }
+}
+
+/// Writes a calendar with a VTODO for every task with a due date, and a
+/// VEVENT for every note that is an event
+///
+/// A note is an event if its `event` front matter is a date, or if it is
+/// `true` and the note has a `date`. Dates can have a time, `end` sets the
+/// end of the event. Only published notes are exported.
+///
+/// UIDs are derived from the note id, and for tasks from their `^block-id`
+/// or their text, so a calendar client subscribed to the file updates the
+/// entries instead of adding new ones on every build.
+pub struct Ical<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> Ical<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    pub fn write_all(&self) -> io::Result<()> {
+        let outfile = self.notebook.outdir().join(ICAL_FILE);
+        println!("Writing to {}", outfile.display());
+        fs::create_dir_all(self.notebook.outdir())?;
+        fs::File::create(&outfile)?.write_all(self.render().as_bytes())
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            String::from("BEGIN:VCALENDAR"),
+            String::from("VERSION:2.0"),
+            String::from("PRODID:-//notes//notes//EN"),
+            String::from("CALSCALE:GREGORIAN"),
+            format!("X-WR-CALNAME:{}", text(self.notebook.title())),
+        ];
+
+        for note in self.notebook.notes().published() {
+            lines.extend(event(note));
+        }
+        let tasks = self.notebook.notes().published().tasks();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for task in tasks.iter().filter(|task| task.due.is_some()) {
+            // Tasks with the same text in a note are told apart by their order
+            let key = task_key(task);
+            let count = seen.entry(key.clone()).or_insert(0);
+            *count += 1;
+            let key = match *count {
+                1 => key,
+                n => format!("{}#{}", key, n),
+            };
+            lines.extend(todo(task, &key));
+        }
+
+        lines.push(String::from("END:VCALENDAR"));
+        lines.iter().map(|line| fold(line)).collect()
+    }
+}
+
+/// VEVENT lines for `note`, if it is an event
+fn event(note: &Note) -> Vec<String> {
+    let start = match note.meta("event").as_deref() {
+        Some("true") | Some("yes") => note.meta("date").and_then(|x| When::parse(&x)),
+        Some(value) => When::parse(value),
+        None => None,
+    };
+    let start = match start {
+        Some(start) => start,
+        None => return vec![],
+    };
+    let end = note.meta("end").and_then(|x| When::parse(&x))
+        // An end date is inclusive, DTEND is not
+        .map(|end| match (start, end) {
+            (When::Day(_), When::Day(date)) => When::Day(date + Duration::days(1)),
+            (_, end) => end,
+        })
+        .unwrap_or_else(|| start.default_end());
+
+    let mut lines = vec![
+        String::from("BEGIN:VEVENT"),
+        format!("UID:{}", uid(note.id())),
+        format!("DTSTAMP:{}", stamp(note)),
+        start.property("DTSTART"),
+        end.property("DTEND"),
+        format!("SUMMARY:{}", text(note.title())),
+    ];
+    if let Some(location) = note.meta("location") {
+        lines.push(format!("LOCATION:{}", text(&location)));
+    }
+    if !note.tags().is_empty() {
+        lines.push(categories(note.tags()));
+    }
+    lines.push(String::from("END:VEVENT"));
+    lines
+}
+
+/// Key for the UID of a task: its note and its `^block-id` if it has one, or
+/// its description otherwise. Unlike the number of the task, this does not
+/// change when tasks are added above it. A block id is never part of the
+/// description, so tasks without one keep their key, and adding one to a
+/// task changes its key once.
+fn task_key(task: &Task) -> String {
+    match &task.block_id {
+        Some(id) => format!("{}#^{}", task.note.id(), id),
+        None => format!("{}#{}", task.note.id(), task.description()),
+    }
+}
+
+/// VTODO lines for a task with a due date, `key` identifies the task
+fn todo(task: &Task, key: &str) -> Vec<String> {
+    let mut lines = vec![
+        String::from("BEGIN:VTODO"),
+        format!("UID:{}", uid(key)),
+        format!("DTSTAMP:{}", stamp(task.note)),
+        When::Day(task.due.unwrap()).property("DUE"),
+        format!("SUMMARY:{}", text(&task.description())),
+        format!("DESCRIPTION:{}", text(&format!("From {}", task.note.title()))),
+        format!("STATUS:{}", if task.done { "COMPLETED" } else { "NEEDS-ACTION" }),
+    ];
+    if let Some(priority) = task.priority {
+        lines.push(format!("PRIORITY:{}", match priority {
+            Priority::High => 1,
+            Priority::Medium => 5,
+            Priority::Low => 9,
+        }));
+    }
+    if !task.tags.is_empty() {
+        lines.push(categories(&task.tags));
+    }
+    lines.push(String::from("END:VTODO"));
+    lines
+}
+
+/// Stable UID for `key`, a 64-bit FNV-1a hash
+fn uid(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}@notes", hash)
+}
+
+/// The time the note changed, in UTC
+fn stamp(note: &Note) -> String {
+    let time: DateTime<Utc> = note.modified().unwrap_or_else(SystemTime::now).into();
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn categories(tags: &[String]) -> String {
+    let tags: Vec<String> = tags.iter().map(|x| text(x)).collect();
+    format!("CATEGORIES:{}", tags.join(","))
+}
+
+/// Escape a TEXT value
+fn text(input: &str) -> String {
+    input.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line into lines of at most 75 bytes, ended by CRLF
+fn fold(line: &str) -> String {
+    let mut output = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            output.push_str("\r\n ");
+            length = 1;
+        }
+        output.push(c);
+        length += c.len_utf8();
+    }
+    output.push_str("\r\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+    use pulldown_cmark::Options;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(text("a, b; c\\d\nnext"), r"a\, b\; c\\d\nnext");
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold("SUMMARY:short"), "SUMMARY:short\r\n");
+
+        let line = format!("SUMMARY:{}", "x".repeat(150));
+        let folded = fold(&line);
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines.iter().map(|x| x.len()).collect::<Vec<_>>(), vec![75, 75, 10, 0]);
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+
+        // Lines are not split inside a character
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        for part in fold(&line).split("\r\n") {
+            assert!(part.len() <= 75);
+        }
+    }
+
+    #[test]
+    fn when() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        assert_eq!(When::parse("2026-10-19"), Some(When::Day(day)));
+        assert_eq!(When::parse(" 2026-10-19T09:30 "),
+                   Some(When::Time(day.and_hms_opt(9, 30, 0).unwrap())));
+        assert_eq!(When::parse("19-10-2026"), None);
+        assert_eq!(When::parse("2026-10-19").unwrap().property("DTSTART"),
+                   "DTSTART;VALUE=DATE:20261019");
+        assert_eq!(When::parse("2026-10-19 09:30").unwrap().default_end().property("DTEND"),
+                   "DTEND:20261019T103000");
+    }
+
+    #[test]
+    fn task_uids() {
+        let notebook = notebook(&[
+            ("a.md", "- [ ] one due:2026-10-19\n- [ ] two due:2026-10-20 ^two\n"),
+            ("b.md", "- [ ] new due:2026-10-01\n- [ ] one due:2026-11-01\n\
+                      - [ ] moved due:2026-10-21 ^two\n"),
+        ]);
+        let options = Options::all();
+        let a = Task::find(notebook.get("a").unwrap(), options);
+        let b = Task::find(notebook.get("b").unwrap(), options);
+
+        // Keys do not depend on the position or the due date of the task
+        assert_eq!(task_key(&a[0]), "a#one");
+        assert_eq!(task_key(&a[1]), "a#^two");
+        assert_eq!(task_key(&b[1]), "b#one");
+        assert_eq!(task_key(&b[2]), "b#^two");
+        assert_eq!(uid("a#one"), uid("a#one"));
+        assert_ne!(uid("a#one"), uid("b#one"));
+    }
+
+    #[test]
+    fn block_ids() {
+        let notebook = notebook(&[
+            ("a.md", concat!("- [ ] Pay rent due:2026-10-01 ^rent\n",
+                             "- [ ] Call due:2026-10-02\n")),
+        ]);
+        let tasks = Task::find(notebook.get("a").unwrap(), Options::all());
+
+        // The block id is in the UID, but not in the summary
+        let lines = todo(&tasks[0], &task_key(&tasks[0]));
+        assert!(lines.contains(&format!("UID:{}", uid("a#^rent"))));
+        assert!(lines.contains(&String::from("SUMMARY:Pay rent")));
+        // Tasks without a block id keep the key of their text
+        let lines = todo(&tasks[1], &task_key(&tasks[1]));
+        assert!(lines.contains(&format!("UID:{}", uid("a#Call"))));
+        assert!(lines.contains(&String::from("SUMMARY:Call")));
+    }
+}
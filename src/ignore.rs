@@ -0,0 +1,226 @@
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::fs;
+
+/// Name of the per-directory ignore files
+pub const IGNORE_FILE: &str = ".notesignore";
+
+/// A single gitignore-style pattern
+pub struct IgnoreRule {
+    regex: Regex,
+    /// Pattern started with `!`, matching paths are included again
+    negated: bool,
+    /// Pattern ended with `/`, only matches directories
+    dir_only: bool,
+    /// Directory the pattern is relative to
+    base: PathBuf,
+    /// Where the pattern came from, e.g. `notes/.notesignore:3`
+    pub source: String,
+    /// The pattern as written
+    pub pattern: String,
+}
+
+impl IgnoreRule {
+    /// Parse a single gitignore-style pattern, relative to `base`. Returns
+    /// `None` for empty lines and comments.
+    pub fn new(pattern: &str, base: &Path, source: &str) -> Option<Self> {
+        let line = pattern.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, glob) = match line.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+
+        // Patterns without a slash match at any depth
+        let anchored = glob.contains('/');
+        let glob = glob.trim_start_matches('/');
+
+        let mut regex = String::from("^");
+        if !anchored {
+            regex.push_str("(?:.*/)?");
+        }
+        regex.push_str(&glob_to_regex(glob));
+        regex.push('$');
+
+        Some(Self {
+            regex: Regex::new(&regex).ok()?,
+            negated,
+            dir_only,
+            base: PathBuf::from(base),
+            source: String::from(source),
+            pattern: String::from(line),
+        })
+    }
+
+    /// Check whether the rule matches `path`. Paths outside of the base
+    /// directory never match.
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        match path.strip_prefix(&self.base) {
+            Ok(rel) => {
+                let rel = rel.components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                self.regex.is_match(&rel)
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+/// Ordered list of ignore rules, later rules take precedence over earlier ones
+#[derive(Default)]
+pub struct IgnoreList {
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreList {
+    pub fn push(&mut self, rule: IgnoreRule) {
+        self.rules.push(rule);
+    }
+
+    /// Read the rules in an ignore file, if it exists. Returns the number of
+    /// rules added, so they can be removed again with `truncate`.
+    pub fn read_file(&mut self, file: &Path) -> usize {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(_) => return 0,
+        };
+        let base = file.with_file_name("");
+
+        let before = self.rules.len();
+        for (i, line) in contents.lines().enumerate() {
+            let source = format!("{}:{}", file.display(), i + 1);
+            if let Some(rule) = IgnoreRule::new(line, &base, &source) {
+                self.rules.push(rule);
+            }
+        }
+        self.rules.len() - before
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Remove all rules after the first `len` rules
+    pub fn truncate(&mut self, len: usize) {
+        self.rules.truncate(len);
+    }
+
+    /// Returns the rule that causes `path` to be ignored, if any
+    pub fn check(&self, path: &Path, is_dir: bool) -> Option<&IgnoreRule> {
+        self.rules.iter().rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .filter(|rule| !rule.negated)
+    }
+}
+
+/// Convert a glob to a regex. `*` and `?` do not match `/`, `**` does.
+fn glob_to_regex(glob: &str) -> String {
+    let mut output = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    output.push_str("(?:.*/)?");
+                } else {
+                    output.push_str(".*");
+                }
+            },
+            '*' => output.push_str("[^/]*"),
+            '?' => output.push_str("[^/]"),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']')
+                    .collect();
+                let class = match class.strip_prefix('!') {
+                    Some(class) => format!("^{}", class),
+                    None => class,
+                };
+                output.push('[');
+                output.push_str(&class.replace('\\', "\\\\"));
+                output.push(']');
+            },
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    output.push_str(&regex::escape(&c.to_string()));
+                }
+            },
+            c => output.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(patterns: &[&str], path: &str, is_dir: bool) -> bool {
+        let base = Path::new("/notes");
+        let mut list = IgnoreList::default();
+        for pattern in patterns {
+            if let Some(rule) = IgnoreRule::new(pattern, base, "test") {
+                list.push(rule);
+            }
+        }
+        list.check(&base.join(path), is_dir).is_some()
+    }
+
+    #[test]
+    fn glob_to_regex() {
+        assert_eq!(super::glob_to_regex("*.md"), r"[^/]*\.md");
+        assert_eq!(super::glob_to_regex("a?c"), "a[^/]c");
+        assert_eq!(super::glob_to_regex("**/draft"), "(?:.*/)?draft");
+        assert_eq!(super::glob_to_regex("a/**"), "a/.*");
+        assert_eq!(super::glob_to_regex("[!ab]x"), "[^ab]x");
+        assert_eq!(super::glob_to_regex(r"\*"), r"\*");
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(ignored(&["*.tmp"], "a/b/c.tmp", false));
+        assert!(!ignored(&["*.tmp"], "a/b/c.md", false));
+        // Patterns with a slash are relative to the base
+        assert!(ignored(&["/drafts"], "drafts", true));
+        assert!(!ignored(&["/drafts"], "a/drafts", true));
+        assert!(ignored(&["a/*/c.md"], "a/b/c.md", false));
+        assert!(!ignored(&["a/*/c.md"], "a/b/x/c.md", false));
+        assert!(ignored(&["a/**/c.md"], "a/b/x/c.md", false));
+        // Directory patterns only match directories
+        assert!(ignored(&["build/"], "build", true));
+        assert!(!ignored(&["build/"], "build", false));
+        // Comments and empty lines are not rules
+        assert!(!ignored(&["# x", ""], "# x", false));
+        // Paths outside the base never match
+        let rule = IgnoreRule::new("*", Path::new("/notes"), "test").unwrap();
+        assert!(!rule.matches(Path::new("/other/note.md"), false));
+    }
+
+    #[test]
+    fn negation() {
+        let patterns = ["*.md", "!keep.md"];
+        assert!(ignored(&patterns, "drop.md", false));
+        assert!(!ignored(&patterns, "keep.md", false));
+        assert!(ignored(&["!keep.md", "*.md"], "keep.md", false));
+    }
+}
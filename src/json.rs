@@ -0,0 +1,31 @@
+//! Minimal JSON output helpers
+use std::fmt::Write;
+
+/// Quote and escape a string as a JSON string
+pub fn string(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() + 2);
+    output.push('"');
+    for c in input.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            // Keep the output safe to embed in a <script> tag
+            '<' => output.push_str("\\u003c"),
+            c if (c as u32) < 0x20 => {
+                write!(output, "\\u{:04x}", c as u32).unwrap();
+            },
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+/// JSON array of strings
+pub fn string_array<S: AsRef<str>>(items: &[S]) -> String {
+    let items: Vec<String> = items.iter().map(|x| string(x.as_ref())).collect();
+    format!("[{}]", items.join(","))
+}
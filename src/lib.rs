@@ -1,18 +1,109 @@
 use pulldown_cmark::{Options};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
 use std::io::{self, Read, Write, Error, ErrorKind};
 use std::fs;
 use std::cell::RefCell;
+use std::time::SystemTime;
+use std::mem;
+use std::rc::Rc;
+use chrono::NaiveDate;
 
-mod parsers;
-mod compiler;
+pub mod parsers;
+pub mod compiler;
+pub mod links;
+pub mod ignore;
+pub mod formats;
+pub mod publish;
+pub mod listing;
+pub mod tags;
+pub mod search;
+pub mod json;
+pub mod query;
+pub mod graph;
+pub mod check;
+pub mod rename;
+pub mod redirect;
+pub mod template;
+pub mod periodic;
+pub mod tasks;
+pub mod ical;
+pub mod board;
+pub mod live_query;
+pub mod schema;
 use compiler::{NoteCompiler, Asset};
+use links::Link;
+use ignore::{IgnoreList, IgnoreRule, IGNORE_FILE};
+use formats::InputFormat;
+use publish::Profile;
+use listing::{IndexGenerator, SortBy};
+use tags::TagPages;
+use search::SearchIndex;
+use search::site::{SiteSearch, SITE_INDEX, SITE_SCRIPT};
+use query::Notes;
+use graph::view::{GraphView, GRAPH_PAGE, GRAPH_SCRIPT};
+use rename::Move;
+use redirect::Redirects;
+use template::Template;
+use periodic::{Calendar, Period, PeriodicIndex, PeriodicNotes, CALENDAR_PAGE};
+use tasks::{Agenda, AGENDA_PAGE};
+use ical::{Ical, ICAL_FILE};
+use board::{BoardPage, BOARD_PAGE};
+use schema::Schema;
+use check::Diagnostic;
 
 struct NotebookConfig {
     basedir: PathBuf,
     outdir: PathBuf,
-    ignore: Vec<PathBuf>,
+    /// Ignore rules from the config, relative to the basedir
+    ignore: IgnoreList,
+    /// Also read `.gitignore` files while scanning
+    gitignore: bool,
+    /// {File extension, format}. Only files with these extensions are notes
+    formats: HashMap<String, Rc<dyn InputFormat>>,
+    /// Decides which notes are compiled
+    profile: Profile,
+    /// Generate index pages for the outdir and its directories
+    index: bool,
+    index_sort: SortBy,
+    /// Generate a page per tag and a tag overview
+    tag_pages: bool,
+    /// Location of the full-text search index, relative to the basedir
+    search_index: PathBuf,
+    /// Write a search index and widget for the compiled site
+    site_search: bool,
+    /// Write the graph page and script
+    graph: bool,
+    /// Size of the local graph shown on note pages, `None` to disable it
+    graph_hops: Option<usize>,
+    /// Colour graph nodes by `folder` or `tag`
+    graph_color: String,
+    /// Also match links ignoring case, whitespace and underscores
+    loose_links: bool,
+    /// Write redirect pages for aliases and previous names of notes
+    redirects: bool,
+    /// Directory with note templates, relative to the basedir
+    templates: PathBuf,
+    /// Directory for notes created with `new_note`, relative to the basedir
+    new_note_dir: PathBuf,
+    /// Path patterns and templates of `notes daily` and `notes weekly`
+    daily: PeriodicNotes,
+    weekly: PeriodicNotes,
+    /// Generate a calendar page of the periodic notes
+    calendar: bool,
+    /// Generate an agenda page of the open tasks
+    agenda: bool,
+    /// Export dated tasks and events as an iCalendar file
+    ical: bool,
+    /// Generate a board page of the notes grouped by `board_field`
+    board: bool,
+    board_field: String,
+    /// Columns shown first on the board, even if they are empty
+    board_columns: Vec<String>,
+    /// Declared front matter fields, checked by `scan_and_add`
+    schema: Option<Schema>,
+    /// Refuse to build when notes do not match the schema
+    strict_schema: bool,
 }
 
 pub struct Notebook {
@@ -21,8 +112,31 @@ pub struct Notebook {
 
     compiler: NoteCompiler,
 
-    // {Note name, note}
+    // {Note id, note}, where the id is the path relative to `basedir` without
+    // extension, e.g. `home/todo`
     notes: HashMap<String, Note>,
+
+    /// Paths skipped by the last scan, with the rule that excluded them
+    ignored: Vec<Ignored>,
+
+    /// Front matter problems found by the last scan
+    schema_problems: Vec<Diagnostic>,
+}
+
+/// A path skipped by `scan_and_add`
+pub struct Ignored {
+    pub path: PathBuf,
+    /// Source of the matching rule, e.g. `notes/.notesignore:3`
+    pub source: String,
+    pub pattern: String,
+}
+
+/// Result of resolving a wikilink to a note
+pub enum Resolved<'a> {
+    Found(&'a Note),
+    /// Multiple notes match the link, contains the candidate ids
+    Ambiguous(Vec<&'a str>),
+    Missing,
 }
 
 impl Notebook {
@@ -37,7 +151,34 @@ impl Notebook {
                 basedir: PathBuf::from(basedir),
 
                 outdir: PathBuf::from(String::from("html")),
-                ignore: vec![],
+                ignore: IgnoreList::default(),
+                gitignore: false,
+                formats: ["md", "org"].iter()
+                    .map(|ext| (ext.to_string(), formats::for_extension(ext)))
+                    .collect(),
+                profile: Profile::default(),
+                index: true,
+                index_sort: SortBy::Title,
+                tag_pages: true,
+                search_index: PathBuf::from(".notes-index"),
+                site_search: true,
+                graph: true,
+                graph_hops: Some(1),
+                graph_color: String::from("folder"),
+                loose_links: false,
+                redirects: true,
+                templates: PathBuf::from("templates"),
+                new_note_dir: PathBuf::new(),
+                daily: PeriodicNotes::new(Period::Daily),
+                weekly: PeriodicNotes::new(Period::Weekly),
+                calendar: true,
+                agenda: true,
+                ical: true,
+                board: true,
+                board_field: String::from("status"),
+                board_columns: vec![],
+                schema: None,
+                strict_schema: false,
             },
             compiler: NoteCompiler {
                 parse_options: Options::all(),
@@ -71,73 +212,531 @@ r#"<!DOCTYPE html>
         {assets}
     </head>
     <body>
+        {periodic}
+
+        {toc}
+
         {content}
+
+        {graph}
     </body>
 </html>"#
                 ),
-                assets: vec![Asset::Css(PathBuf::from("test.css"))],
+                assets: vec![
+                    Asset::Css(PathBuf::from("test.css")),
+                    Asset::BuiltJs(PathBuf::from(SITE_SCRIPT)),
+                    Asset::BuiltJs(PathBuf::from(GRAPH_SCRIPT)),
+                ],
                 parsers: vec![
                     // Box::new(parsers::FlashcardParser::new()),
                 ],
+                heading_anchors: false,
             },
             notes: HashMap::new(),
+            ignored: vec![],
+            schema_problems: vec![],
         }
     }
 
-    /// Add path to the ignore list
+    /// Set the output directory, relative to the basedir
+    pub fn set_outdir(&mut self, outdir: &Path) {
+        self.config.outdir = PathBuf::from(outdir);
+    }
+
+    /// Add a gitignore-style glob pattern to the ignore list
     ///
-    /// `scan_dir_and_add` will ignore paths in this list
-    pub fn add_ignore(&mut self, ignore: &Path) {
-        if let Ok(path) = PathBuf::from(ignore).canonicalize() {
-            self.config.ignore.push(path);
+    /// The pattern is relative to the basedir. `scan_dir_and_add` will skip
+    /// paths matching any of these patterns, as well as the patterns found in
+    /// `.notesignore` files.
+    pub fn add_ignore(&mut self, pattern: &str) {
+        if let Some(rule) = IgnoreRule::new(pattern, &self.config.basedir,
+                                            "config") {
+            self.config.ignore.push(rule);
         }
     }
 
+    /// Set the file extensions of notes, each using the builtin format for
+    /// that extension
+    ///
+    /// This replaces the default `md` and `org` extensions.
+    pub fn set_extensions(&mut self, extensions: &[String]) {
+        self.config.formats = extensions.iter()
+            .map(|ext| ext.trim_start_matches('.'))
+            .map(|ext| (ext.to_string(), formats::for_extension(ext)))
+            .collect();
+    }
+
+    /// Read files with the given extension using `format`
+    pub fn set_format(&mut self, extension: &str, format: Rc<dyn InputFormat>) {
+        self.config.formats.insert(
+            extension.trim_start_matches('.').to_string(), format);
+    }
+
+    /// Enable or disable the generated index pages, and set the order of the
+    /// notes on them
+    pub fn set_index(&mut self, enabled: bool, sort: SortBy) {
+        self.config.index = enabled;
+        self.config.index_sort = sort;
+    }
+
+    /// Enable or disable the generated tag pages
+    pub fn set_tag_pages(&mut self, enabled: bool) {
+        self.config.tag_pages = enabled;
+    }
+
+    /// Enable or disable the search index and widget of the compiled site
+    pub fn set_site_search(&mut self, enabled: bool) {
+        self.config.site_search = enabled;
+        self.set_built_script(SITE_SCRIPT, enabled);
+    }
+
+    /// Enable or disable the graph page and the local graph on note pages.
+    /// `hops` is the size of the local graph, 0 to disable it. Nodes are
+    /// coloured by `color`, which is `folder` or `tag`.
+    pub fn set_graph(&mut self, enabled: bool, hops: usize, color: &str) {
+        self.config.graph = enabled;
+        self.config.graph_hops = Some(hops).filter(|&x| enabled && x > 0);
+        self.config.graph_color = String::from(color);
+        self.set_built_script(GRAPH_SCRIPT, enabled);
+    }
+
+    /// Size of the local graph on note pages, if enabled
+    pub fn graph_hops(&self) -> Option<usize> {
+        self.config.graph_hops
+    }
+
+    pub fn graph_color(&self) -> &str {
+        &self.config.graph_color
+    }
+
+    /// Also resolve links ignoring case, whitespace and underscores, so
+    /// `[machine learning]` finds `Machine_Learning`
+    pub fn set_loose_links(&mut self, enabled: bool) {
+        self.config.loose_links = enabled;
+    }
+
+    /// Enable or disable redirect pages for note aliases and previous names
+    pub fn set_redirects(&mut self, enabled: bool) {
+        self.config.redirects = enabled;
+    }
+
+    /// Set the directory with note templates, relative to the basedir. It is
+    /// left out when scanning for notes.
+    pub fn set_templates(&mut self, dir: &Path) {
+        self.config.templates = PathBuf::from(dir);
+    }
+
+    /// Set the directory new notes are created in, relative to the basedir
+    pub fn set_new_note_dir(&mut self, dir: &Path) {
+        self.config.new_note_dir = PathBuf::from(dir);
+    }
+
+    /// Set where the notes of `period` live, see `PeriodicNotes`. Fails if
+    /// `pattern` is not a valid `strftime` format.
+    pub fn set_periodic(&mut self, period: Period, pattern: &str,
+                        template: Option<&str>) -> io::Result<()> {
+        if !template::is_valid_format(pattern) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!(
+                "invalid {} path {:?}, expected a strftime format like {:?}",
+                period.name(), pattern, PeriodicNotes::new(period).pattern)));
+        }
+        let notes = match period {
+            Period::Daily => &mut self.config.daily,
+            Period::Weekly => &mut self.config.weekly,
+        };
+        notes.pattern = String::from(pattern);
+        notes.template = template.map(String::from);
+        Ok(())
+    }
+
+    pub fn periodic(&self, period: Period) -> &PeriodicNotes {
+        match period {
+            Period::Daily => &self.config.daily,
+            Period::Weekly => &self.config.weekly,
+        }
+    }
+
+    /// Enable or disable the calendar page of the periodic notes
+    pub fn set_calendar(&mut self, enabled: bool) {
+        self.config.calendar = enabled;
+    }
+
+    /// Enable or disable the agenda page of the open tasks
+    pub fn set_agenda(&mut self, enabled: bool) {
+        self.config.agenda = enabled;
+    }
+
+    /// Enable or disable the iCalendar export of tasks and events
+    pub fn set_ical(&mut self, enabled: bool) {
+        self.config.ical = enabled;
+    }
+
+    /// Group notes and tasks on the board by front matter `field`, starting
+    /// with `columns`
+    pub fn set_board(&mut self, enabled: bool, field: &str, columns: &[String]) {
+        self.config.board = enabled;
+        self.config.board_field = String::from(field);
+        self.config.board_columns = columns.to_vec();
+    }
+
+    pub fn board_field(&self) -> &str {
+        &self.config.board_field
+    }
+
+    pub fn board_columns(&self) -> &[String] {
+        &self.config.board_columns
+    }
+
+    /// Check the front matter of the notes against the schema file at
+    /// `path`, relative to the basedir. With `strict` the build fails if a
+    /// note does not match it.
+    pub fn set_schema(&mut self, path: &Path, strict: bool) -> io::Result<()> {
+        self.config.schema = Some(Schema::open(&self.config.basedir.join(path))?);
+        self.config.strict_schema = strict;
+        Ok(())
+    }
+
+    /// Problems with the front matter of `note`, empty without a schema
+    pub fn check_schema(&self, note: &Note) -> Vec<Diagnostic> {
+        match &self.config.schema {
+            Some(schema) => schema.validate(note, &self.config.profile.exclude),
+            None => vec![],
+        }
+    }
+
+    /// Front matter problems found by the last `scan_and_add`
+    pub fn schema_problems(&self) -> &[Diagnostic] {
+        &self.schema_problems
+    }
+
+    /// Add a `#` link to every heading of the compiled notes
+    pub fn set_heading_anchors(&mut self, enabled: bool) {
+        self.compiler.heading_anchors = enabled;
+    }
+
+    /// Include or leave out a script generated by the build in the pages
+    fn set_built_script(&mut self, script: &str, enabled: bool) {
+        self.compiler.assets.retain(|asset| !matches!(asset,
+            Asset::BuiltJs(path) if path == Path::new(script)));
+        if enabled {
+            self.compiler.assets.push(Asset::BuiltJs(PathBuf::from(script)));
+        }
+    }
+
+    /// Set the location of the full-text search index, relative to the basedir
+    pub fn set_search_index(&mut self, file: &Path) {
+        self.config.search_index = PathBuf::from(file);
+    }
+
+    /// Load the full-text search index, and update it with the notes that
+    /// changed since the last time. With `rebuild`, all notes are indexed
+    /// again.
+    pub fn search_index(&self, rebuild: bool) -> io::Result<SearchIndex> {
+        let file = self.config.basedir.join(&self.config.search_index);
+        let mut index = match rebuild {
+            true => SearchIndex::default(),
+            false => SearchIndex::load(&file)?,
+        };
+
+        let (updated, removed) = index.update(self);
+        if updated > 0 || removed > 0 {
+            index.save(&file)?;
+        }
+        Ok(index)
+    }
+
+    pub fn index_sort(&self) -> SortBy {
+        self.config.index_sort
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn compiler(&self) -> &NoteCompiler {
+        &self.compiler
+    }
+
+    pub fn basedir(&self) -> &Path {
+        &self.config.basedir
+    }
+
+    /// Directory the HTML is written to
+    pub fn outdir(&self) -> PathBuf {
+        self.config.basedir.join(&self.config.outdir)
+    }
+
+    /// Set the publish profile, which decides which notes are compiled
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.config.profile = profile;
+    }
+
+    /// Check whether a note is published under the current profile
+    pub fn is_published(&self, note: &Note) -> bool {
+        self.config.profile.includes(note)
+    }
+
+    /// Also honor `.gitignore` files while scanning
+    pub fn set_use_gitignore(&mut self, gitignore: bool) {
+        self.config.gitignore = gitignore;
+    }
+
+    /// Paths skipped by the last `scan_and_add`, and why
+    pub fn ignored(&self) -> &[Ignored] {
+        &self.ignored
+    }
+
     /// Get a reference to a note in the notebook
     ///
-    /// This function looks through the local list of notes. Make sure to `add`
-    /// the notes before calling `find`
-    pub fn get(&self, note_name: &str) -> Option<&Note> {
-        self.notes.get(note_name)
+    /// `note_id` is the path of the note relative to the basedir, without
+    /// extension (e.g. `home/todo`). This function looks through the local
+    /// list of notes. Make sure to `add` the notes before calling `get`
+    pub fn get(&self, note_id: &str) -> Option<&Note> {
+        self.notes.get(note_id)
     }
 
     /// Get mutable reference to a note in the notebook
-    pub fn get_mut(&mut self, note_name: &str) -> Option<&mut Note> {
-        self.notes.get_mut(note_name)
+    pub fn get_mut(&mut self, note_id: &str) -> Option<&mut Note> {
+        self.notes.get_mut(note_id)
+    }
+
+    /// Query the notes in the notebook, see `Notes`
+    pub fn notes(&self) -> Notes<'_> {
+        Notes::new(self)
+    }
+
+    /// Ids of all notes in the notebook
+    pub fn note_ids(&self) -> Vec<&str> {
+        self.notes.keys().map(|id| id.as_str()).collect()
+    }
+
+    /// All tags in the notebook, including the parents of hierarchical tags,
+    /// with the number of notes per tag
+    pub fn tags(&self) -> BTreeMap<String, usize> {
+        let mut output = BTreeMap::new();
+        for note in self.notes.values() {
+            let mut tags: Vec<&str> = note.tags.iter()
+                .flat_map(|tag| tags::with_parents(tag))
+                .collect();
+            tags.sort_unstable();
+            tags.dedup();
+            for tag in tags {
+                *output.entry(String::from(tag)).or_insert(0) += 1;
+            }
+        }
+        output
+    }
+
+    /// Resolve a wikilink target to a note
+    ///
+    /// A link matches a note if it equals the note id, or if it is a suffix of
+    /// the id on a directory boundary. So `todo` matches both `work/todo` and
+    /// `home/todo`, and `home/todo` only matches the latter. A link resolves if
+    /// exactly one note matches, or if one of the matches is the link itself.
+    ///
+    /// Links that match no id are looked up in the `aliases` of the notes.
+    /// With loose links enabled, ids and aliases are then compared ignoring
+    /// case, whitespace and underscores.
+    pub fn resolve(&self, link: &str) -> Resolved<'_> {
+        let notes: Vec<(&str, &Note)> = self.notes.iter()
+            .map(|(id, note)| (id.as_str(), note))
+            .collect();
+        let mut matches = self.link_targets(&notes, link);
+
+        match matches.len() {
+            0 => Resolved::Missing,
+            1 => Resolved::Found(&self.notes[matches[0]]),
+            _ => {
+                matches.sort_unstable();
+                Resolved::Ambiguous(matches)
+            },
+        }
+    }
+
+    /// The ids in `notes` that wikilink target `link` matches, following the
+    /// rules of `resolve`. The ids do not have to be the current ones, which
+    /// lets a move check the links against the ids after it.
+    pub(crate) fn link_targets<'a>(&self, notes: &[(&'a str, &Note)], link: &str)
+        -> Vec<&'a str>
+    {
+        let mut matches = link_matches(notes.iter().map(|(id, _)| *id), link);
+        if matches.is_empty() {
+            matches = alias_matches(notes, link.trim(), str::to_string);
+        }
+        if matches.is_empty() && self.config.loose_links {
+            let link = loose_name(link);
+            let ids: Vec<(String, &str)> = notes.iter()
+                .map(|(id, _)| (loose_name(id), *id))
+                .collect();
+            matches = ids.iter().filter(|(x, _)| *x == link)
+                .map(|(_, id)| *id)
+                .collect();
+            if matches.is_empty() {
+                let suffix = format!("/{}", link);
+                matches = ids.iter().filter(|(x, _)| x.ends_with(&suffix))
+                    .map(|(_, id)| *id)
+                    .collect();
+            }
+            if matches.is_empty() {
+                matches = alias_matches(notes, &link, loose_name);
+            }
+        }
+        matches
+    }
+
+    /// Id of the note at `path`, which is its path relative to the basedir
+    /// without extension, using `/` as separator.
+    pub fn note_id(&self, path: &Path) -> String {
+        let path = path.strip_prefix(&self.config.basedir).unwrap_or(path);
+        path.with_extension("").components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// All wikilinks in a note
+    pub fn links(&self, note: &Note) -> Vec<Link> {
+        links::find_links(&note.read(), self.compiler.parse_options)
+    }
+
+    /// All wikilinks in the notebook that match more than one note
+    ///
+    /// Returns the id of the note containing the link, the link, and the
+    /// candidate note ids.
+    pub fn ambiguous_links(&self) -> Vec<(&str, Link, Vec<&str>)> {
+        let mut output = vec![];
+        for (id, note) in &self.notes {
+            for link in self.links(note) {
+                if let Resolved::Ambiguous(candidates) =
+                    self.resolve(&link.target)
+                {
+                    output.push((id.as_str(), link, candidates));
+                }
+            }
+        }
+        output.sort_by(|a, b| a.0.cmp(b.0));
+        output
+    }
+
+    /// Files the enabled generators write to the root of the outdir, next
+    /// to the compiled notes
+    pub fn generated_files(&self) -> Vec<&'static str> {
+        let mut files = vec![];
+        if self.config.site_search {
+            files.extend([SITE_SCRIPT, SITE_INDEX]);
+        }
+        if self.config.graph {
+            files.extend([GRAPH_PAGE, GRAPH_SCRIPT]);
+        }
+        if self.config.calendar {
+            files.push(CALENDAR_PAGE);
+        }
+        if self.config.agenda {
+            files.push(AGENDA_PAGE);
+        }
+        if self.config.ical {
+            files.push(ICAL_FILE);
+        }
+        if self.config.board {
+            files.push(BOARD_PAGE);
+        }
+        files
+    }
+
+    /// Whether `file`, relative to the outdir, is written by one of the
+    /// enabled generators: a file from `generated_files`, a directory index
+    /// or a tag page
+    pub fn is_generated(&self, file: &str) -> bool {
+        self.generated_files().contains(&file)
+            || (self.config.index && file.rsplit('/').next() == Some("index.html"))
+            || (self.config.tag_pages && file.starts_with("tags/"))
+    }
+
+    /// Published notes whose page is overwritten by a generated file, with
+    /// the file
+    pub fn generated_collisions(&self) -> Vec<(&str, &'static str)> {
+        let outdir = self.outdir();
+        let files = self.generated_files();
+        let mut output = vec![];
+        for note in self.notes().published() {
+            let outfile = self.outfile(note);
+            if let Some(file) = files.iter().find(|x| outdir.join(x) == outfile) {
+                output.push((note.id(), *file));
+            }
+        }
+        output
     }
 
     /// Add a note's absolute path to the notebook
     fn add_abs(&mut self, path: &Path) -> io::Result<&Note> {
-        let note = Note::open(&path)?;
-        let note_name = path.file_stem().unwrap();
-        let note_name = String::from(note_name.to_str().unwrap());
+        let format = self.format(path)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput,
+                    "Tried to add a note with an unknown extension."))?;
+        let mut note = Note::open_with(path, format)?;
+        let note_id = self.note_id(path);
+        note.id = note_id.clone();
 
-        Ok(self.notes.entry(note_name).or_insert(note))
+        if let Some(other) = self.notes.get(&note_id) {
+            println!("Warning: {} has the same id as {}, skipping it",
+                     path.display(), other.path.display());
+        }
+        Ok(self.notes.entry(note_id).or_insert(note))
+    }
+
+    /// The input format of the note at `path`, based on its extension
+    fn format(&self, path: &Path) -> Option<Rc<dyn InputFormat>> {
+        let ext = path.extension()?.to_str()?;
+        self.config.formats.get(ext).cloned()
     }
 
     /// Add note's relative path to notebook 
     ///
     /// Searches for the filename starting in the config.basedir
-    fn add(&mut self, filename: &Path) -> io::Result<&Note> {
+    pub fn add(&mut self, filename: &Path) -> io::Result<&Note> {
         let mut path = PathBuf::from(&self.config.basedir);
         path.push(filename);
         self.add_abs(&path)
     }
 
-    /// Scans the `config.basedir` recursively and adds all markdown files found
+    /// Scans the `config.basedir` recursively and adds all note files found
     // TODO: See if I can return an iterator over the notes, and add helper
     // functions that can `add` and `grep` and stuff on the iterators
     pub fn scan_and_add(&mut self) {
         // TODO: Fix this clone...
         let basedir = self.config.basedir.clone();
-        self.scan_dir_and_add(Path::new(&basedir));
+        self.ignored.clear();
+
+        // Never pick up our own output
+        let mut rules = mem::take(&mut self.config.ignore);
+        let len = rules.len();
+        let outdir = format!("/{}/", self.config.outdir.to_string_lossy());
+        if let Some(rule) = IgnoreRule::new(&outdir, &basedir, "outdir") {
+            rules.push(rule);
+        }
+        let templates = format!("/{}/", self.config.templates.to_string_lossy());
+        if let Some(rule) = IgnoreRule::new(&templates, &basedir, "templates") {
+            rules.push(rule);
+        }
+
+        self.scan_dir_and_add(Path::new(&basedir), &mut rules);
+
+        rules.truncate(len);
+        self.config.ignore = rules;
+
+        let mut ids = self.note_ids();
+        ids.sort_unstable();
+        self.schema_problems = ids.iter()
+            .flat_map(|id| self.check_schema(&self.notes[*id]))
+            .collect();
     }
 
-    /// Recursively scans the given `dir` for markdown files not on the
-    /// ignorelist.
-    fn scan_dir_and_add(&mut self, dir: &Path) {
-        if self.config.ignore.contains(&dir.canonicalize().unwrap()) {
-            return;
+    /// Recursively scans the given `dir` for note files not matching any of
+    /// the ignore `rules`. Ignore files in `dir` apply to the whole subtree.
+    fn scan_dir_and_add(&mut self, dir: &Path, rules: &mut IgnoreList) {
+        let len = rules.len();
+        rules.read_file(&dir.join(IGNORE_FILE));
+        if self.config.gitignore {
+            rules.read_file(&dir.join(".gitignore"));
         }
 
         let entries = fs::read_dir(dir).unwrap()
@@ -148,34 +747,234 @@ r#"<!DOCTYPE html>
 
         // Recurse through directories
         for dir in dirs {
-            self.scan_dir_and_add(&dir);
+            if !self.is_ignored(&dir, true, rules) {
+                self.scan_dir_and_add(&dir, rules);
+            }
         }
 
-        // Go over each markdown file in current folder
-        for entry in entries.iter().filter(|x| match x.extension() {
-                Some(ext) => ext == "md",
-                None => false,
-            })
-        {
-            if self.config.ignore.contains(&entry.canonicalize().unwrap()) {
-                return;
+        // Go over each note file in current folder
+        for entry in entries {
+            if self.format(&entry).is_none() {
+                continue;
+            }
+            if !self.is_ignored(&entry, false, rules) {
+                self.add_abs(&entry).unwrap();
             }
+        }
+
+        rules.truncate(len);
+    }
+
+    /// Check `path` against the ignore rules, and remember it if it is ignored
+    fn is_ignored(&mut self, path: &Path, is_dir: bool, rules: &IgnoreList)
+        -> bool
+    {
+        match rules.check(path, is_dir) {
+            Some(rule) => {
+                self.ignored.push(Ignored {
+                    path: PathBuf::from(path),
+                    source: rule.source.clone(),
+                    pattern: rule.pattern.clone(),
+                });
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Create the note `name` from a template, and add it to the notebook
+    ///
+    /// `name` is the title of the note, and its path relative to the new
+    /// note directory without extension, e.g. `physics/Lecture 3`. The
+    /// `template` is looked up in the templates directory, with or without
+    /// extension. Without one the `default` template is used, or a builtin
+    /// one if there is none. `prompt` answers the questions in the template,
+    /// see `Template`.
+    pub fn new_note<F>(&mut self, name: &str, template: Option<&str>, prompt: F)
+        -> io::Result<&Note>
+        where F: FnMut(&str) -> String
+    {
+        let dir = self.config.new_note_dir.clone();
+        self.create_note(&dir, name, template, prompt)
+    }
+
+    /// Create the note `name` in `dir`, relative to the basedir, see
+    /// `new_note`
+    fn create_note<F>(&mut self, dir: &Path, name: &str, template: Option<&str>,
+                      prompt: F) -> io::Result<&Note>
+        where F: FnMut(&str) -> String
+    {
+        let template = match (self.template(template.unwrap_or("default")),
+                              template) {
+            (Some(path), _) => Template::open(&path)?,
+            (None, None) => Template::builtin(),
+            (None, Some(name)) => return Err(Error::new(ErrorKind::NotFound,
+                format!("Template {} not found.", name))),
+        };
+
+        let name = name.trim_matches('/');
+        let title = name.rsplit('/').next().unwrap();
+        let path = self.config.basedir.join(dir)
+            .join(format!("{}.{}", name, template.extension));
+        if path.exists() {
+            return Err(Error::new(ErrorKind::AlreadyExists,
+                format!("{} already exists.", path.display())));
+        }
+
+        let contents = template.render(title, prompt);
+        fs::create_dir_all(path.with_file_name(""))?;
+        fs::write(&path, contents)?;
+        self.add_abs(&path)
+    }
 
-            self.add_abs(entry).unwrap();
+    /// The note of `period` containing `date`, which is created if it does
+    /// not exist yet. Returns whether the note was created.
+    pub fn periodic_note<F>(&mut self, period: Period, date: NaiveDate, prompt: F)
+        -> io::Result<(&Note, bool)>
+        where F: FnMut(&str) -> String
+    {
+        let notes = self.periodic(period);
+        let id = notes.id(date);
+        if self.notes.contains_key(&id) {
+            return Ok((&self.notes[&id], false));
         }
+
+        let template = notes.template.clone()
+            .or_else(|| self.template(period.name()).map(|_| String::from(period.name())));
+        let note = self.create_note(Path::new(""), &id, template.as_deref(), prompt)?;
+        Ok((note, true))
+    }
+
+    /// Path of the template `name`, which may leave out the extension
+    fn template(&self, name: &str) -> Option<PathBuf> {
+        let path = self.config.basedir.join(&self.config.templates).join(name);
+        if path.is_file() {
+            return Some(path);
+        }
+
+        let mut extensions: Vec<&String> = self.config.formats.keys().collect();
+        extensions.sort_unstable();
+        extensions.into_iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|path| path.is_file())
+    }
+
+    /// Set front matter `key` of a markdown note to `value`, replacing the
+    /// old value or adding the key. Notes without front matter get one.
+    pub fn set_meta(&mut self, note_id: &str, key: &str, value: &str) -> io::Result<()> {
+        let note = self.get(note_id).ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound, format!("unknown note {}", note_id)))?;
+        if note.format.name() != "markdown" {
+            return Err(io::Error::new(io::ErrorKind::Unsupported,
+                format!("cannot change the metadata of {} notes", note.format.name())));
+        }
+
+        let contents = fs::read_to_string(&note.path)?;
+        fs::write(&note.path, set_yaml_value(&contents, key, value))?;
+        note.metadata.borrow_mut().insert(String::from(key), String::from(value));
+        Ok(())
+    }
+
+    /// Move a note to `to`, relative to the basedir, and rewrite the links to
+    /// it in all notes. The compiled HTML of the note is moved along.
+    ///
+    /// If `to` is a directory the note keeps its file name, without an
+    /// extension it keeps its extension. With `dry_run` nothing is changed,
+    /// but the summary shows what would be.
+    pub fn move_note(&mut self, note_id: &str, to: &Path, dry_run: bool)
+        -> io::Result<Move>
+    {
+        let note = self.get(note_id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound,
+                    "Tried to move an unknown note."))?;
+        let from = note.path.clone();
+
+        let into_dir = to.to_string_lossy().ends_with('/');
+        let mut to = self.config.basedir.join(to);
+        if into_dir || to.is_dir() {
+            to.push(from.file_name().unwrap());
+        } else if to.extension().is_none() {
+            to.set_extension(from.extension().unwrap_or_default());
+        }
+        if self.format(&to).is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput,
+                "Tried to move a note to an unknown extension."));
+        }
+        let to_id = self.note_id(&to);
+        if to.exists() || self.notes.contains_key(&to_id) {
+            return Err(Error::new(ErrorKind::AlreadyExists,
+                format!("{} already exists.", to_id)));
+        }
+
+        let rewrites = rename::rewrite_links(self, note_id, &to_id, &to)?;
+        let summary = Move {
+            from: from.clone(),
+            to: to.clone(),
+            edits: rewrites.edits,
+            skipped: rewrites.skipped,
+        };
+        if dry_run {
+            return Ok(summary);
+        }
+
+        for (path, contents) in rewrites.files {
+            fs::write(path, contents)?;
+        }
+        fs::create_dir_all(to.with_file_name(""))?;
+        fs::rename(&from, &to)?;
+
+        let old_outfile = self.outfile(note);
+        let mut note = self.notes.remove(note_id).unwrap();
+        note.path = to;
+        note.id = to_id.clone();
+        let outfile = self.outfile(&note);
+        if old_outfile.exists() {
+            fs::create_dir_all(outfile.with_file_name(""))?;
+            fs::rename(&old_outfile, &outfile)?;
+        }
+        self.notes.insert(to_id, note);
+
+        Ok(summary)
+    }
+
+    /// Location of the compiled HTML of a note
+    pub fn outfile(&self, note: &Note) -> PathBuf {
+        self.outdir().join(
+            note.path.strip_prefix(&self.config.basedir).unwrap()
+            .with_extension("html")
+        )
+    }
+
+    /// Compile `note` if it is published under the current profile, and
+    /// remove its compiled HTML otherwise. Returns whether it was compiled.
+    /// `periodic` is the `PeriodicIndex` of the notebook, made once for all
+    /// notes of a build.
+    pub fn publish_note(&self, note: &Note, periodic: &PeriodicIndex)
+        -> io::Result<bool>
+    {
+        if self.is_published(note) {
+            self.write_note(note, periodic)?;
+            return Ok(true);
+        }
+        let outfile = self.outfile(note);
+        if outfile.exists() {
+            println!("Removing unpublished {}", outfile.display());
+            fs::remove_file(&outfile)?;
+        }
+        Ok(false)
     }
 
     /// Compile a given note into HTML, and save it in the outdir at the same
     /// relative location as the markdown files.
-    pub fn compile_note(&self, note_name: &str) -> io::Result<()> {
-        let note = self.get(note_name)
-            .ok_or(Error::new(ErrorKind::NotFound,
+    pub fn compile_note(&self, note_id: &str) -> io::Result<()> {
+        let note = self.get(note_id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound,
                     "Tried to compile an unknown note."))?;
+        self.write_note(note, &PeriodicIndex::new(self))
+    }
 
-        let outfile = self.config.basedir.join(&self.config.outdir).join(
-            note.path.strip_prefix(&self.config.basedir).unwrap()
-            .with_extension("html")
-        );
+    fn write_note(&self, note: &Note, periodic: &PeriodicIndex) -> io::Result<()> {
+        let outfile = self.outfile(note);
 
         fs::create_dir_all(outfile.with_file_name(""))?;
 
@@ -183,17 +982,75 @@ r#"<!DOCTYPE html>
         // as a String
         println!("Writing to {}", &outfile.to_str().unwrap());
         let mut file = fs::File::create(&outfile)?;
-        file.write_all(self.compiler.to_decorated_html(&note, &self).as_bytes())?;
+        file.write_all(self.compiler.to_decorated_html(note, self, periodic)
+                       .as_bytes())?;
         Ok(())
     }
 
-    /// Compile all notes into HTML
+    /// Compile all notes published under the current profile into HTML
+    ///
+    /// HTML of excluded notes left over from earlier builds is removed.
     // TODO: Maybe let this return an iterator over converted notes and have a
     // correspinding iter.save function to save the data to a file. This way the
     // save code can be used to serve the data with an internal webserver
     pub fn compile_all(&self) -> io::Result<()> {
-        for note_name in self.notes.keys() {
-            self.compile_note(note_name)?;
+        if !self.schema_problems.is_empty() {
+            println!("Warning: {} front matter problem(s):", self.schema_problems.len());
+            for problem in &self.schema_problems {
+                println!("  {}", problem);
+            }
+            if self.config.strict_schema {
+                return Err(Error::new(ErrorKind::InvalidData,
+                                      "notes do not match the schema"));
+            }
+        }
+
+        let periodic = PeriodicIndex::new(self);
+        for note in self.notes.values() {
+            self.publish_note(note, &periodic)?;
+        }
+
+        if self.config.index {
+            IndexGenerator::new(self).write_all()?;
+        }
+        if self.config.tag_pages {
+            TagPages::new(self).write_all()?;
+        }
+        if self.config.site_search {
+            SiteSearch::new(self).write_all()?;
+        }
+        if self.config.graph {
+            GraphView::new(self).write_all()?;
+        }
+        if self.config.redirects {
+            Redirects::new(self).write_all()?;
+        }
+        if self.config.calendar {
+            Calendar::new(self).write_all()?;
+        }
+        if self.config.agenda {
+            Agenda::new(self).write_all()?;
+        }
+        if self.config.ical {
+            Ical::new(self).write_all()?;
+        }
+        if self.config.board {
+            BoardPage::new(self).write_all()?;
+        }
+
+        for (id, file) in self.generated_collisions() {
+            println!("Warning: the page of {} is overwritten by the generated {}, \
+                     rename the note or turn off the page", id, file);
+        }
+
+        let ambiguous = self.ambiguous_links();
+        if !ambiguous.is_empty() {
+            println!("Warning: {} ambiguous link(s), use a longer path to \
+                     disambiguate:", ambiguous.len());
+            for (id, link, candidates) in ambiguous {
+                println!("  {}: [{}] matches {}", id, link.target,
+                         candidates.join(", "));
+            }
         }
         Ok(())
     }
@@ -202,17 +1059,34 @@ r#"<!DOCTYPE html>
 pub struct Note {
     /// full path to the note file
     pub path: PathBuf,
-    
-    // /// Last modified timestamp
-    // modified: SystemTime,
+    /// Path relative to the notebook's basedir without extension, set when
+    /// the note is added to a notebook
+    id: String,
+
     // contents: String,
 
     metadata: RefCell<HashMap<String, String>>,
     title: String,
+    /// Tags from the front matter and inline `#tags`
+    tags: Vec<String>,
+    /// Other names of the note from the `aliases` front matter, used to
+    /// resolve links
+    aliases: Vec<String>,
+
+    /// Markup language of the file, converted to markdown when reading
+    format: Rc<dyn InputFormat>,
 }
 
 impl Note {
+    /// Open a markdown note
     pub fn open(path: &Path) -> io::Result<Self> {
+        Self::open_with(path, Rc::new(formats::MarkdownFormat))
+    }
+
+    /// Open a note written in the given input `format`
+    pub fn open_with(path: &Path, format: Rc<dyn InputFormat>)
+        -> io::Result<Self>
+    {
         // TODO: Maybe do reading the file lazily
 
         let mut file = fs::File::open(path)?;
@@ -220,26 +1094,79 @@ impl Note {
 
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
+        let mut contents = format.to_markdown(&contents);
 
         let mut metadata = split_yaml_pairs(&strip_yaml(&mut contents));
 
         let title = metadata.remove("title").unwrap_or_else(|| {
-            contents.lines().next().unwrap().strip_prefix("# ")
+            contents.lines().next().unwrap_or("").strip_prefix("# ")
                 .unwrap_or(path.file_stem().unwrap().to_str().unwrap())
                 .to_string()
         });
 
+        let mut tags = metadata.get("tags")
+            .map_or_else(Vec::new, |x| tags::split_tags(x));
+        tags.extend(tags::find_tags(&contents));
+        tags.sort_unstable();
+        tags.dedup();
+
+        let aliases = metadata.get("aliases")
+            .map_or_else(Vec::new, |x| split_yaml_list(x));
+
         let note = Self {
             path: PathBuf::from(path),
+            id: path.file_stem().unwrap().to_string_lossy().to_string(),
             metadata: RefCell::new(metadata),
-            title: title,
+            title,
+            tags,
+            aliases,
+            format,
         };
 
         Ok(note)
     }
 
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    /// Last modified timestamp of the note file
+    pub fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Check whether the note has `tag` or one of its subtags
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|x| tags::matches(x, tag))
+    }
+
+    /// Get a front matter value. Only contains the `title` after the note
+    /// has been `read`.
+    pub fn meta(&self, key: &str) -> Option<String> {
+        self.metadata.borrow().get(key).cloned()
+    }
+
+    /// Check whether a boolean front matter flag is set, e.g. `draft: true`
+    pub fn flag(&self, key: &str) -> bool {
+        matches!(self.meta(key).as_deref(), Some("true") | Some("yes"))
+    }
+
+    /// Read the note as markdown, without its front matter
     pub fn read(&self) -> String {
-        let mut contents = fs::read_to_string(&self.path).unwrap();
+        let contents = fs::read_to_string(&self.path).unwrap();
+        let mut contents = self.format.to_markdown(&contents);
 
         let metadata = split_yaml_pairs(
             &strip_yaml(&mut contents));
@@ -249,6 +1176,69 @@ impl Note {
     }
 }
 
+/// Name used for loose link matching, lowercase with whitespace and
+/// underscores collapsed into a single space
+fn loose_name(name: &str) -> String {
+    name.trim_matches('/').to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '_')
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ids of the `notes` with an alias equal to `link`, after applying `key` to
+/// both
+fn alias_matches<'a, F>(notes: &[(&'a str, &Note)], link: &str, key: F)
+    -> Vec<&'a str>
+    where F: Fn(&str) -> String
+{
+    let link = key(link);
+    notes.iter()
+        .filter(|(_, note)| note.aliases.iter().any(|x| key(x) == link))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// The note ids in `ids` that wikilink target `link` matches, see
+/// `Notebook::resolve`. Only the exact match is returned if there is one.
+pub(crate) fn link_matches<'a, I>(ids: I, link: &str) -> Vec<&'a str>
+    where I: Iterator<Item = &'a str>
+{
+    let link = link.trim_matches('/');
+    let suffix = format!("/{}", link);
+
+    let mut matches = vec![];
+    for id in ids {
+        if id == link {
+            return vec![id];
+        }
+        if id.ends_with(&suffix) {
+            matches.push(id);
+        }
+    }
+    matches
+}
+
+/// Length in bytes of the YAML front matter that `strip_yaml` removes from
+/// `input`, including the `---` lines
+pub(crate) fn yaml_len(input: &str) -> usize {
+    let mut lines = input.split_inclusive('\n');
+    let is_fence = |line: &str| line.trim_end_matches(['\r', '\n']) == "---";
+    match lines.next() {
+        Some(first) if is_fence(first) => {
+            let mut len = first.len();
+            for line in lines {
+                len += line.len();
+                if is_fence(line) {
+                    break;
+                }
+            }
+            len
+        },
+        _ => 0,
+    }
+}
+
 fn strip_yaml(input: &mut String) -> Vec<String> {
     // Separate YAML header from markdown content
     let mut lines = input.lines().peekable();
@@ -273,21 +1263,205 @@ fn strip_yaml(input: &mut String) -> Vec<String> {
     output
 }
 
+/// Set `key` in the YAML front matter of `contents`. A block list below
+/// the old value is replaced as well.
+fn set_yaml_value(contents: &str, key: &str, value: &str) -> String {
+    let line = format!("{}: {}", key, value);
+    let mut lines: Vec<&str> = contents.lines().collect();
+    let end = match lines.first() {
+        Some(&"---") => lines.iter().skip(1).position(|&x| x == "---").map(|i| i + 1),
+        _ => None,
+    };
+    let end = match end {
+        Some(end) => end,
+        None => return format!("---\n{}\n---\n{}", line, contents),
+    };
+
+    let prefix = format!("{}:", key);
+    match lines[1..end].iter().position(|x| x.starts_with(&prefix)) {
+        Some(i) => {
+            let start = i + 1;
+            let items = lines[start + 1..end].iter()
+                .take_while(|x| x.trim_start().starts_with("- "))
+                .count();
+            lines.splice(start..start + 1 + items, [line.as_str()]);
+        },
+        None => lines.insert(end, &line),
+    }
+
+    let mut output = lines.join("\n");
+    if contents.ends_with('\n') {
+        output.push('\n');
+    }
+    output
+}
+
 pub fn split_yaml_pairs(input: &[String]) -> HashMap<String, String> {
     // TODO: Implement some YAML crate to actually parse yaml
 
     // Loop over each key value pair
     // TODO: See if I can clean this up some more
     let mut output: HashMap<String, String> = HashMap::new();
-    for split in input.iter().map(|x| x.splitn(2, ":").map(|x| x.trim())) {
+    let mut block_list: Option<(String, Vec<&str>)> = None;
+    for line in input {
+        // Items of a block list (`- item` lines) below an empty key are
+        // stored as a flow list, `[item, item]`
+        if let Some(item) = line.trim_start().strip_prefix("- ") {
+            if let Some((_, items)) = &mut block_list {
+                items.push(item.trim());
+            }
+            continue;
+        }
+        if let Some((k, items)) = block_list.take() {
+            output.insert(k, format!("[{}]", items.join(", ")));
+        }
+
         // Unpack iterator over two values, into k, v pair
-        let a: Vec<&str> = split.collect();
-        match &a[..] {
-            &[k, v, ..] =>
-                output.insert(String::from(k), String::from(v)),
+        let a: Vec<&str> = line.splitn(2, ':').map(|x| x.trim()).collect();
+        match a[..] {
+            [k, ""] =>
+                block_list = Some((String::from(k), vec![])),
+            [k, v, ..] =>
+                { output.insert(String::from(k), String::from(v)); },
             _ => continue,
         };
     }
+    if let Some((k, items)) = block_list {
+        output.insert(k, format!("[{}]", items.join(", ")));
+    }
 
     output
 }
+
+/// Split a YAML flow list (`[a, b]`) or comma separated value into its items
+pub fn split_yaml_list(input: &str) -> Vec<String> {
+    let input = input.trim();
+    let input = input.strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .unwrap_or(input);
+
+    input.split(',')
+        .map(|x| x.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Notebook in a new temporary directory with `files`, as paths relative
+    /// to the basedir and their contents
+    pub(crate) fn notebook(files: &[(&str, &str)]) -> Notebook {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let basedir = std::env::temp_dir().join(format!("notes-test-{}-{}",
+            std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+        for (path, contents) in files {
+            let path = basedir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let mut notebook = Notebook::new("Test", basedir.to_str().unwrap());
+        for (path, _) in files {
+            notebook.add(Path::new(path)).unwrap();
+        }
+        notebook
+    }
+
+    fn resolved(notebook: &Notebook, link: &str) -> Option<String> {
+        match notebook.resolve(link) {
+            Resolved::Found(note) => Some(String::from(note.id())),
+            Resolved::Ambiguous(ids) => Some(format!("ambiguous {}", ids.join(" "))),
+            Resolved::Missing => None,
+        }
+    }
+
+    #[test]
+    fn link_matches_suffix() {
+        let ids = ["work/todo", "home/todo", "todo", "notes/mytodo"];
+
+        assert_eq!(link_matches(ids.iter().copied(), "todo"), vec!["todo"]);
+        assert_eq!(link_matches(ids.iter().copied(), "home/todo"), vec!["home/todo"]);
+        assert_eq!(link_matches(ids.iter().copied(), "/work/todo/"), vec!["work/todo"]);
+        assert_eq!(link_matches(ids[..2].iter().copied(), "todo"),
+                   vec!["work/todo", "home/todo"]);
+        assert!(link_matches(ids.iter().copied(), "odo").is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        let notebook = notebook(&[
+            ("work/todo.md", "# Work\n"),
+            ("home/todo.md", "# Home\n"),
+            ("science/Physics.md", "# Physics\n"),
+        ]);
+
+        assert_eq!(resolved(&notebook, "Physics").as_deref(), Some("science/Physics"));
+        assert_eq!(resolved(&notebook, "home/todo").as_deref(), Some("home/todo"));
+        assert_eq!(resolved(&notebook, "todo").as_deref(),
+                   Some("ambiguous home/todo work/todo"));
+        assert_eq!(resolved(&notebook, "physics"), None);
+        assert_eq!(resolved(&notebook, "Chemistry"), None);
+    }
+
+    #[test]
+    fn resolve_aliases() {
+        let notebook = notebook(&[
+            ("cs/machine-learning.md", "---\naliases: [ML, Machine Learning]\n---\n# ML\n"),
+            ("ML.md", "# Notes on ML\n"),
+            ("stats.md", "---\naliases: [Learning]\n---\n"),
+            ("other.md", "---\naliases: [Learning]\n---\n"),
+        ]);
+
+        // Ids go before aliases
+        assert_eq!(resolved(&notebook, "ML").as_deref(), Some("ML"));
+        assert_eq!(resolved(&notebook, " Machine Learning ").as_deref(),
+                   Some("cs/machine-learning"));
+        assert_eq!(resolved(&notebook, "machine learning"), None);
+        assert_eq!(resolved(&notebook, "Learning").as_deref(),
+                   Some("ambiguous other stats"));
+    }
+
+    #[test]
+    fn resolve_loose() {
+        let mut notebook = notebook(&[
+            ("science/Quantum_Physics.md", "# QP\n"),
+            ("cs/machine-learning.md", "---\naliases: [Machine Learning]\n---\n"),
+        ]);
+        assert_eq!(resolved(&notebook, "quantum physics"), None);
+
+        notebook.set_loose_links(true);
+        assert_eq!(resolved(&notebook, "quantum physics").as_deref(),
+                   Some("science/Quantum_Physics"));
+        assert_eq!(resolved(&notebook, "Science/quantum  PHYSICS").as_deref(),
+                   Some("science/Quantum_Physics"));
+        assert_eq!(resolved(&notebook, "machine_learning").as_deref(),
+                   Some("cs/machine-learning"));
+    }
+
+    #[test]
+    fn yaml_lengths() {
+        assert_eq!(yaml_len("---\ntags: [x]\n---\nBody"), 18);
+        assert_eq!(yaml_len("---\r\na: b\r\n---\r\nBody"), 16);
+        assert_eq!(yaml_len("Body\n---\n"), 0);
+        assert_eq!(yaml_len("---\nunclosed"), 12);
+    }
+
+    #[test]
+    fn set_yaml_values() {
+        // Replace a value, and the block list below it
+        assert_eq!(set_yaml_value("---\nstatus: todo\ntitle: A\n---\nText\n", "status", "done"),
+                   "---\nstatus: done\ntitle: A\n---\nText\n");
+        assert_eq!(set_yaml_value("---\nstatus:\n  - a\n  - b\ntags: [x]\n---\n",
+                                  "status", "done"),
+                   "---\nstatus: done\ntags: [x]\n---\n");
+        // Other keys starting with the same name are left alone
+        assert_eq!(set_yaml_value("---\nstatuses: a\n---\nText", "status", "done"),
+                   "---\nstatuses: a\nstatus: done\n---\nText");
+        // Notes without front matter get it
+        assert_eq!(set_yaml_value("# Title\n", "status", "done"),
+                   "---\nstatus: done\n---\n# Title\n");
+    }
+}
@@ -0,0 +1,288 @@
+use pulldown_cmark::{Options, BrokenLink, Event, LinkType, Tag};
+use regex::Regex;
+use pulldown_cmark::Parser as CmarkParser;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// A wikilink found in a note, e.g. `[Note]`, `[[home/todo]]` or
+/// `[[Note#Section|shown text]]`
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// Note reference, without fragment or alias
+    pub target: String,
+    /// Part after the `#`, if any
+    pub fragment: Option<String>,
+    /// Part after the `|`, if any
+    pub alias: Option<String>,
+    /// Byte range of the reference in the note contents
+    pub span: Range<usize>,
+}
+
+impl Link {
+    /// Split a raw link reference into target, fragment and alias.
+    pub fn parse(reference: &str, span: Range<usize>) -> Self {
+        let (reference, alias) = match reference.split_once('|') {
+            Some((r, a)) => (r, Some(a.trim().to_string())),
+            None => (reference, None),
+        };
+        let (target, fragment) = match reference.split_once('#') {
+            Some((t, f)) => (t, Some(f.trim().to_string())),
+            None => (reference, None),
+        };
+
+        Self {
+            target: target.trim().to_string(),
+            fragment,
+            alias,
+            span,
+        }
+    }
+
+    /// Id of the element the fragment points to, the heading slug or the
+    /// `^block-id`
+    pub fn anchor(&self) -> Option<String> {
+        let fragment = self.fragment.as_deref()?;
+        match fragment.starts_with('^') {
+            true => Some(String::from(fragment)),
+            false => Some(slug(fragment)),
+        }
+    }
+
+    /// Text to show for this link when no alias is given. Links to blocks
+    /// show the note.
+    pub fn display(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        match &self.fragment {
+            Some(fragment) if fragment.starts_with('^') && !self.target.is_empty() =>
+                self.target.clone(),
+            Some(fragment) if self.target.is_empty() => fragment.clone(),
+            Some(fragment) => format!("{} > {}", self.target, fragment),
+            None => self.target.clone(),
+        }
+    }
+}
+
+/// Find all wikilinks in the given markdown.
+///
+/// Wikilinks are shortcut references without a matching link definition, so
+/// the markdown parser is used to find them. This way links inside code are
+/// skipped.
+pub fn find_links(content: &str, options: Options) -> Vec<Link> {
+    let mut links = vec![];
+
+    {
+        let func = &mut |link: BrokenLink| {
+            if let LinkType::Shortcut = link.link_type {
+                if !is_callout(content, link.span.start) {
+                    links.push(Link::parse(link.reference, link.span));
+                }
+            }
+            None
+        };
+
+        let parser = CmarkParser::new_with_broken_link_callback(
+            content, options, Some(func));
+        parser.for_each(drop);
+    }
+
+    links
+}
+
+/// Find the `[!type]` marker of a callout at the start of `text`, with `+`
+/// or `-` after it if the callout can be folded. Returns the type, the fold
+/// sign or an empty string, and the offset of the title after the marker.
+pub fn callout_marker(text: &str) -> Option<(&str, &str, usize)> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^\[!([A-Za-z-]+)\]([+-]?)\s*").unwrap());
+    let cap = re.captures(text)?;
+    Some((cap.get(1).unwrap().as_str(), cap.get(2).unwrap().as_str(),
+          cap.get(0).unwrap().end()))
+}
+
+/// Whether the shortcut reference at `start` in `content` is the marker of a
+/// callout: the first thing in a block quote
+fn is_callout(content: &str, start: usize) -> bool {
+    let line = &content[content[..start].rfind('\n').map_or(0, |i| i + 1)..start];
+    line.contains('>')
+        && line.chars().all(|c| c == '>' || c.is_whitespace())
+        && callout_marker(&content[start..]).is_some()
+}
+
+/// Heading id for `text`: lowercase letters and digits, with dashes between
+/// words. `Some Heading: Part 2` becomes `some-heading-part-2`.
+pub fn slug(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .map(|word| word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<String>())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Find the `^block-id` marker at the end of the text of a paragraph or list
+/// item. Returns the offset of the marker, including the whitespace before
+/// it, and the id without `^`.
+pub fn block_marker(text: &str) -> Option<(usize, &str)> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"(?:^|\s+)\^([A-Za-z0-9-]+)\s*$").unwrap());
+    let cap = re.captures(text)?;
+    Some((cap.get(0).unwrap().start(), cap.get(1).unwrap().as_str()))
+}
+
+/// All blocks with a `^block-id` marker, with the id and the byte range of
+/// the paragraph or list item
+pub fn find_blocks(content: &str, options: Options)
+    -> Vec<(String, Range<usize>)>
+{
+    let mut output = vec![];
+    let mut open: Vec<Range<usize>> = vec![];
+    let mut text = String::new();
+
+    let mut found = |text: &str, open: &[Range<usize>]| {
+        if let (Some((_, id)), Some(range)) = (block_marker(text), open.last()) {
+            output.push((String::from(id), range.clone()));
+        }
+    };
+
+    for (event, range) in CmarkParser::new_ext(content, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Item) =>
+                open.push(range),
+            Event::End(Tag::Paragraph) | Event::End(Tag::Item) => {
+                found(&text, &open);
+                open.pop();
+            },
+            // The marker of a list item goes before its sublists
+            Event::Start(Tag::List(_)) => found(&text, &open),
+            Event::Text(t) => {
+                text.push_str(&t);
+                continue;
+            },
+            _ => (),
+        }
+        text.clear();
+    }
+
+    output
+}
+
+/// The file a link destination points to, without fragment or query. `None`
+/// for urls and links within the page.
+pub fn local_path(dest: &str) -> Option<String> {
+    let dest = dest.split(['#', '?']).next().unwrap();
+    if dest.is_empty() {
+        return None;
+    }
+
+    // Urls start with a scheme, e.g. `https:` or `mailto:`
+    if let Some((scheme, _)) = dest.split_once(':') {
+        if !scheme.is_empty() && !scheme.contains('/')
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        {
+            return None;
+        }
+    }
+
+    Some(dest.replace("%20", " "))
+}
+
+/// Remove `.` and `..` components from `path` without touching the disk
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut output = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !output.pop() {
+                    output.push("..");
+                }
+            },
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Some Heading: Part 2"), "some-heading-part-2");
+        assert_eq!(slug("  snake_case and-dashes  "), "snake-case-and-dashes");
+        assert_eq!(slug("C++ & Rust!"), "c-rust");
+        assert_eq!(slug("Ünïcode Wörds"), "ünïcode-wörds");
+        assert_eq!(slug("?!"), "");
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(Link::parse("Note#Some Section", 0..0).anchor().as_deref(),
+                   Some("some-section"));
+        assert_eq!(Link::parse("Note#^block-1", 0..0).anchor().as_deref(),
+                   Some("^block-1"));
+        assert_eq!(Link::parse("Note", 0..0).anchor(), None);
+    }
+
+    #[test]
+    fn callout_markers() {
+        assert_eq!(callout_marker("[!warning] Title"), Some(("warning", "", 11)));
+        assert_eq!(callout_marker("[!tip]-"), Some(("tip", "-", 7)));
+        assert_eq!(callout_marker("[not] a callout"), None);
+        assert_eq!(callout_marker("text [!note]"), None);
+
+        // Callout markers are not links
+        let markdown = "> [!warning] Careful [Note]\n\n[!info] and [[Other]]\n";
+        let targets: Vec<String> = find_links(markdown, Options::all()).into_iter()
+            .map(|x| x.target)
+            .collect();
+        assert_eq!(targets, vec!["Note", "!info", "Other"]);
+    }
+
+    #[test]
+    fn block_markers() {
+        assert_eq!(block_marker("Some text ^abc-1"), Some((9, "abc-1")));
+        assert_eq!(block_marker("Text \t^id  \n"), Some((4, "id")));
+        assert_eq!(block_marker("^only"), Some((0, "only")));
+        assert_eq!(block_marker("text^id"), None);
+        assert_eq!(block_marker("text ^id and more"), None);
+        assert_eq!(block_marker("text ^snake_case"), None);
+    }
+
+    #[test]
+    fn blocks() {
+        let markdown = "Para ^p1\n\n\
+                        - item ^i1\n  - sub ^s1\n- plain\n\n\
+                        Not^n1\n\n\
+                        `code ^c1`\n\n\
+                        See `code` ^after\n\n\
+                        ```\nfenced ^f1\n```\n";
+        let blocks: Vec<(String, &str)> = find_blocks(markdown, Options::all())
+            .into_iter()
+            .map(|(id, range)| (id, &markdown[range]))
+            .collect();
+        assert_eq!(blocks, vec![
+            (String::from("p1"), "Para ^p1\n"),
+            (String::from("i1"), "- item ^i1\n  - sub ^s1\n"),
+            (String::from("s1"), "- sub ^s1\n"),
+            (String::from("after"), "See `code` ^after\n"),
+        ]);
+    }
+
+    #[test]
+    fn display() {
+        let display = |reference| Link::parse(reference, 0..0).display();
+        assert_eq!(display("Note#^blk"), "Note");
+        assert_eq!(display("#^blk"), "^blk");
+        assert_eq!(display("Note#^blk|shown"), "shown");
+        assert_eq!(display("Note#Part"), "Note > Part");
+        assert_eq!(display("#Part"), "Part");
+        assert_eq!(display(" Note "), "Note");
+    }
+}
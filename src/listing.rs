@@ -0,0 +1,226 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+
+/// Order of the notes on index pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortBy {
+    Title,
+    /// Newest first by the `date` metadata, notes without a date go last
+    Date,
+}
+
+impl SortBy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "title" => Some(SortBy::Title),
+            "date" => Some(SortBy::Date),
+            _ => None,
+        }
+    }
+
+    /// Sort notes, ties are broken by title
+    pub fn sort(&self, notes: &mut Vec<(&str, &Note)>) {
+        let by_title = |a: &Note, b: &Note|
+            a.title.to_lowercase().cmp(&b.title.to_lowercase());
+
+        match self {
+            SortBy::Title => notes.sort_by(|a, b| by_title(a.1, b.1)),
+            SortBy::Date => notes.sort_by(|a, b| {
+                match (a.1.meta("date"), b.1.meta("date")) {
+                    (Some(x), Some(y)) => y.cmp(&x),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }.then_with(|| by_title(a.1, b.1))
+            }),
+        }
+    }
+}
+
+/// Generates an `index.html` for the outdir and every directory in it,
+/// listing the published notes and subdirectories.
+pub struct IndexGenerator<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> IndexGenerator<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// All directories containing published notes, as `/` separated paths
+    /// relative to the basedir. The root is the empty string.
+    fn dirs(&self) -> BTreeSet<String> {
+        let mut dirs = BTreeSet::new();
+        dirs.insert(String::new());
+
+        for (id, _) in self.published() {
+            let mut dir = parent(id);
+            while !dir.is_empty() && dirs.insert(String::from(dir)) {
+                dir = parent(dir);
+            }
+        }
+        dirs
+    }
+
+    fn published(&self) -> impl Iterator<Item = (&'a str, &'a Note)> {
+        let notebook = self.notebook;
+        notebook.note_ids().into_iter()
+            .map(move |id| (id, notebook.get(id).unwrap()))
+            .filter(move |(_, note)| notebook.is_published(note))
+    }
+
+    /// Write the index pages of all directories. Directories with a published
+    /// hand-written `index` note are skipped.
+    pub fn write_all(&self) -> io::Result<()> {
+        let outdir = self.notebook.outdir();
+
+        for dir in self.dirs() {
+            let index = join(&dir, "index");
+            let handwritten = self.notebook.get(&index)
+                .filter(|note| self.notebook.is_published(note));
+            if handwritten.is_some() {
+                continue;
+            }
+
+            let outfile = outdir.join(&index).with_extension("html");
+            fs::create_dir_all(outfile.with_file_name(""))?;
+
+            println!("Writing to {}", outfile.display());
+            let mut file = fs::File::create(&outfile)?;
+            file.write_all(self.render(&dir).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Render the index page of `dir`
+    pub fn render(&self, dir: &str) -> String {
+        let dirs = self.dirs();
+        let subdirs = dirs.iter().filter(|x| !x.is_empty() && parent(x) == dir);
+
+        let mut notes: Vec<_> = self.published()
+            .filter(|(id, _)| parent(id) == dir)
+            .collect();
+        self.notebook.index_sort().sort(&mut notes);
+
+        let title = match dir.rsplit('/').next() {
+            Some(name) if !name.is_empty() => name,
+            _ => self.notebook.title(),
+        };
+
+        let mut html = format!("<h1>{}</h1>\n", escape(title));
+        html.push_str("<ul class=\"index\">\n");
+        if !dir.is_empty() {
+            html.push_str("<li class=\"dir\"><a href=\"../index.html\">..</a></li>\n");
+        }
+        for subdir in subdirs {
+            let name = subdir.rsplit('/').next().unwrap();
+            html.push_str(&format!(
+                "<li class=\"dir\"><a href=\"{}/index.html\">{}/</a></li>\n",
+                escape(name), escape(name)));
+        }
+        for (id, note) in notes {
+            let name = id.rsplit('/').next().unwrap();
+            let date = match note.meta("date") {
+                Some(date) => format!(" <span class=\"date\">{}</span>",
+                                      escape(&date)),
+                None => String::new(),
+            };
+            html.push_str(&format!("<li><a href=\"{}.html\">{}</a>{}</li>\n",
+                escape(name), escape(&note.title), date));
+        }
+        html.push_str("</ul>\n");
+
+        let depth = if dir.is_empty() { 0 } else { dir.matches('/').count() + 1 };
+        self.notebook.compiler().to_page(title, &html, depth, &[], self.notebook)
+    }
+}
+
+/// Parent directory of a `/` separated path, empty for the root
+pub fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Join two `/` separated paths
+pub fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn paths() {
+        assert_eq!(parent("a/b/c"), "a/b");
+        assert_eq!(parent("a"), "");
+        assert_eq!(join("", "index"), "index");
+        assert_eq!(join("a/b", "index"), "a/b/index");
+        assert_eq!(SortBy::from_name("date"), Some(SortBy::Date));
+        assert_eq!(SortBy::from_name("size"), None);
+    }
+
+    #[test]
+    fn dirs() {
+        let notebook = notebook(&[
+            ("top.md", ""),
+            ("a/b/c/deep.md", ""),
+            ("draft/wip.md", "---\ndraft: true\n---\n"),
+        ]);
+        let dirs: Vec<String> = IndexGenerator::new(&notebook).dirs()
+            .into_iter().collect();
+
+        assert_eq!(dirs, vec!["", "a", "a/b", "a/b/c"]);
+    }
+
+    #[test]
+    fn render() {
+        let mut notebook = notebook(&[
+            ("zeta.md", "---\ntitle: Zeta\ndate: 2020-01-01\n---\n"),
+            ("alpha.md", "---\ntitle: <Alpha>\n---\n"),
+            ("sub/one.md", ""),
+            ("sub/deeper/two.md", ""),
+        ]);
+        let page = IndexGenerator::new(&notebook).render("");
+        let list = &page[page.find("<ul class=\"index\">").unwrap()..];
+        assert!(list.starts_with(concat!(
+            "<ul class=\"index\">\n",
+            "<li class=\"dir\"><a href=\"sub/index.html\">sub/</a></li>\n",
+            "<li><a href=\"alpha.html\">&lt;Alpha&gt;</a></li>\n",
+            "<li><a href=\"zeta.html\">Zeta</a> <span class=\"date\">2020-01-01</span></li>\n",
+            "</ul>\n")), "{}", list);
+
+        let page = IndexGenerator::new(&notebook).render("sub");
+        assert!(page.contains("<h1>sub</h1>"));
+        assert!(page.contains("<a href=\"../index.html\">..</a>"));
+        assert!(page.contains("<a href=\"deeper/index.html\">deeper/</a>"));
+        assert!(page.contains("<a href=\"one.html\">one</a>"));
+        assert!(!page.contains("two.html"));
+
+        notebook.set_index(true, SortBy::Date);
+        let page = IndexGenerator::new(&notebook).render("");
+        assert!(page.find("zeta.html").unwrap() < page.find("alpha.html").unwrap());
+    }
+
+    #[test]
+    fn handwritten_index() {
+        let notebook = notebook(&[
+            ("index.md", "# Home"),
+            ("sub/note.md", ""),
+        ]);
+        IndexGenerator::new(&notebook).write_all().unwrap();
+
+        let outdir = notebook.outdir();
+        assert!(!outdir.join("index.html").exists());
+        assert!(outdir.join("sub/index.html").exists());
+    }
+}
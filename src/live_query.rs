@@ -0,0 +1,618 @@
+use chrono::Local;
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+use crate::listing::parent;
+use crate::query::Notes;
+use crate::tags;
+use crate::tasks::Task;
+
+/// A query in a ```` ```query ```` block, evaluated when the note is
+/// compiled
+///
+/// ```text
+/// [list | table FIELD, ... | tasks] [from SOURCE ...] [where CONDITION]
+///     [sort FIELD [asc|desc], ...] [limit N]
+/// ```
+///
+/// - Sources are `tag:#name` (or `#name`) and `folder:dir` (or `"dir"`), a
+///   note has to match all of them
+/// - Conditions compare fields with `=`, `!=`, `<`, `<=`, `>` and `>=`, and
+///   combine them with `and`, `or`, `not` (or `!`) and `( )`. A field on its
+///   own is true if it is set and not `false`. `today` is the current date.
+/// - Note fields are the front matter keys, `title`, `id`, `folder` and
+///   `tags`. Tasks have `text`, `done`, `due`, `priority`, `people`, `tags`
+///   and `note`, and the fields of their note.
+///
+/// Only published notes are listed, as the others have no page to link to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveQuery {
+    mode: Mode,
+    from: Vec<Source>,
+    filter: Option<Condition>,
+    /// Fields to sort by, and whether the order is descending
+    sort: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    List,
+    /// Table with a column per field
+    Table(Vec<String>),
+    Tasks,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    Tag(String),
+    Folder(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Compare(String, Op, String),
+    Set(String),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+pub struct LiveQueryError(String);
+
+impl fmt::Display for LiveQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid query: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Not,
+    Op(Op),
+    Word { text: String, quoted: bool },
+}
+
+impl Token {
+    /// Check whether this is the unquoted keyword `name`
+    fn is(&self, name: &str) -> bool {
+        matches!(self, Token::Word { text, quoted: false } if text.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Keywords that start a clause
+const CLAUSES: &[&str] = &["from", "where", "sort", "limit"];
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+impl LiveQuery {
+    pub fn parse(input: &str) -> Result<Self, LiveQueryError> {
+        let mut tokens = lex(input).into_iter().peekable();
+        let mut query = Self {
+            mode: Mode::List,
+            from: vec![],
+            filter: None,
+            sort: vec![],
+            limit: None,
+        };
+
+        match tokens.peek() {
+            Some(token) if token.is("list") => {
+                tokens.next();
+            },
+            Some(token) if token.is("tasks") => {
+                tokens.next();
+                query.mode = Mode::Tasks;
+            },
+            Some(token) if token.is("table") => {
+                tokens.next();
+                let fields = match at_clause(&mut tokens) {
+                    true => vec![],
+                    false => parse_fields(&mut tokens)?,
+                };
+                query.mode = Mode::Table(fields.into_iter().map(|(field, _)| field).collect());
+            },
+            _ => (),
+        }
+
+        while let Some(token) = tokens.next() {
+            if token.is("from") {
+                query.from.extend(parse_sources(&mut tokens)?);
+            } else if token.is("where") {
+                query.filter = Some(parse_or(&mut tokens)?);
+            } else if token.is("sort") {
+                query.sort = parse_fields(&mut tokens)?;
+            } else if token.is("limit") {
+                query.limit = match tokens.next() {
+                    Some(Token::Word { text, .. }) => Some(text.parse().map_err(|_|
+                        LiveQueryError(format!("limit {:?} is not a number", text)))?),
+                    _ => return Err(LiveQueryError(String::from("limit needs a number"))),
+                };
+            } else {
+                return Err(LiveQueryError(format!("unexpected {}", describe(&token))));
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// Rows matching the query, sorted and limited
+    fn rows<'a>(&self, notebook: &'a Notebook) -> Vec<Row<'a>> {
+        let mut notes: Notes = notebook.notes().published();
+        for source in &self.from {
+            notes = match source {
+                Source::Tag(tag) => notes.with_tag(tag),
+                Source::Folder(dir) => notes.under(dir),
+            };
+        }
+
+        let mut rows: Vec<Row> = match self.mode {
+            Mode::Tasks => notes.tasks().into_iter().map(Row::Task).collect(),
+            _ => notes.map(Row::Note).collect(),
+        };
+        if let Some(filter) = &self.filter {
+            rows.retain(|row| filter.matches(row));
+        }
+
+        if self.sort.is_empty() && self.mode != Mode::Tasks {
+            rows.sort_by_key(|row| row.note().title().to_lowercase());
+        }
+        rows.sort_by(|a, b| {
+            self.sort.iter()
+                .map(|(field, descending)| {
+                    let order = compare_fields(a.field(field), b.field(field));
+                    if *descending { order.reverse() } else { order }
+                })
+                .find(|x| x.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+        rows
+    }
+
+    /// HTML of the results, for a page that is `root` away from the outdir
+    /// root
+    pub fn render(&self, notebook: &Notebook, root: &str) -> String {
+        let rows = self.rows(notebook);
+        if rows.is_empty() {
+            return String::from("<p class=\"query-results\">No results</p>\n");
+        }
+
+        let link = |row: &Row| match row {
+            Row::Note(note) => format!("<a href=\"{}{}.html\">{}</a>",
+                root, escape(note.id()), escape(note.title())),
+            Row::Task(task) => format!("<a href=\"{}{}.html#task-{}\">{}</a>",
+                root, escape(task.note.id()), task.number,
+                escape(&task.description())),
+        };
+
+        let mut html = String::new();
+        match &self.mode {
+            Mode::Table(fields) => {
+                html.push_str("<table class=\"query-results\">\n<tr><th>Note</th>");
+                for field in fields {
+                    html.push_str(&format!("<th>{}</th>", escape(field)));
+                }
+                html.push_str("</tr>\n");
+                for row in &rows {
+                    html.push_str(&format!("<tr><td>{}</td>", link(row)));
+                    for field in fields {
+                        let value = row.field(field).unwrap_or_default();
+                        html.push_str(&format!("<td>{}</td>",
+                                               escape(&list_items(&value).join(", "))));
+                    }
+                    html.push_str("</tr>\n");
+                }
+                html.push_str("</table>\n");
+            },
+            Mode::List => {
+                html.push_str("<ul class=\"query-results\">\n");
+                for row in &rows {
+                    html.push_str(&format!("<li>{}</li>\n", link(row)));
+                }
+                html.push_str("</ul>\n");
+            },
+            Mode::Tasks => {
+                html.push_str("<ul class=\"query-results tasks\">\n");
+                for row in &rows {
+                    let done = matches!(row, Row::Task(task) if task.done);
+                    html.push_str(&format!(concat!(
+                        "<li><input disabled=\"\" type=\"checkbox\"{}/> {} ",
+                        "<span class=\"task-note\">{}</span></li>\n"),
+                        if done { " checked=\"\"" } else { "" }, link(row),
+                        escape(row.note().title())));
+                }
+                html.push_str("</ul>\n");
+            },
+        }
+        html
+    }
+}
+
+/// A note or task that a query selects
+enum Row<'a> {
+    Note(&'a Note),
+    Task(Task<'a>),
+}
+
+impl<'a> Row<'a> {
+    fn note(&self) -> &'a Note {
+        match self {
+            Row::Note(note) => note,
+            Row::Task(task) => task.note,
+        }
+    }
+
+    /// Value of `field`, lists are `[a, b]` like in the front matter
+    fn field(&self, field: &str) -> Option<String> {
+        if let Row::Task(task) = self {
+            let value = match field {
+                "text" => Some(task.description()),
+                "done" => Some(task.done.to_string()),
+                "due" => task.due.map(|x| x.to_string()),
+                "priority" => task.priority.map(|x| String::from(x.name())),
+                "people" | "person" => Some(format!("[{}]", task.people.join(", "))),
+                "tags" => Some(format!("[{}]", task.tags.join(", "))),
+                "note" => Some(String::from(task.note.title())),
+                _ => None,
+            };
+            if value.is_some() {
+                return value;
+            }
+        }
+
+        let note = self.note();
+        match field {
+            "title" => Some(String::from(note.title())),
+            "id" => Some(String::from(note.id())),
+            "folder" => Some(String::from(parent(note.id()))),
+            "tags" => Some(format!("[{}]", note.tags().join(", "))),
+            _ => note.meta(field),
+        }
+    }
+}
+
+impl Condition {
+    fn matches(&self, row: &Row) -> bool {
+        match self {
+            Condition::Compare(field, op, value) => {
+                let today = Local::now().date_naive().to_string();
+                let value = if value == "today" { today.as_str() } else { value };
+                match row.field(field) {
+                    Some(actual) if field == "tags" && matches!(op, Op::Eq | Op::Ne) => {
+                        let found = list_items(&actual).iter()
+                            .any(|tag| tags::matches(tag, value));
+                        found == (*op == Op::Eq)
+                    },
+                    Some(actual) => compare(&actual, *op, value),
+                    None => *op == Op::Ne,
+                }
+            },
+            Condition::Set(field) => row.field(field).is_some_and(|value| {
+                !matches!(value.trim(), "" | "false" | "no" | "[]")
+            }),
+            Condition::Not(condition) => !condition.matches(row),
+            Condition::And(a, b) => a.matches(row) && b.matches(row),
+            Condition::Or(a, b) => a.matches(row) || b.matches(row),
+        }
+    }
+}
+
+/// Compare a field value with `value`. Lists match `=` if one of their items
+/// does.
+fn compare(actual: &str, op: Op, value: &str) -> bool {
+    let items = list_items(actual);
+    if actual.trim_start().starts_with('[') && matches!(op, Op::Eq | Op::Ne) {
+        let found = items.iter().any(|x| x.eq_ignore_ascii_case(value));
+        return found == (op == Op::Eq);
+    }
+
+    let order = compare_values(actual, value);
+    match op {
+        Op::Eq => order == Ordering::Equal,
+        Op::Ne => order != Ordering::Equal,
+        Op::Lt => order == Ordering::Less,
+        Op::Le => order != Ordering::Greater,
+        Op::Gt => order == Ordering::Greater,
+        Op::Ge => order != Ordering::Less,
+    }
+}
+
+/// Numbers compare as numbers, everything else as text ignoring case, which
+/// also orders `YYYY-MM-DD` dates
+fn compare_values(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.trim().to_lowercase().cmp(&b.trim().to_lowercase()),
+    }
+}
+
+/// Sort order of two field values, missing values go last
+fn compare_fields(a: Option<String>, b: Option<String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare_values(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Items of a `[a, b]` list, or the value itself
+fn list_items(value: &str) -> Vec<String> {
+    match value.trim_start().starts_with('[') {
+        true => crate::split_yaml_list(value),
+        false => vec![String::from(value)],
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word { text, .. } => format!("{:?}", text),
+        Token::Open => String::from("("),
+        Token::Close => String::from(")"),
+        Token::Comma => String::from(","),
+        Token::Not => String::from("!"),
+        Token::Op(op) => format!("{:?}", op),
+    }
+}
+
+/// Check whether the next token starts a new clause
+fn at_clause(tokens: &mut Tokens) -> bool {
+    tokens.peek().is_none_or(|token| CLAUSES.iter().any(|x| token.is(x)))
+}
+
+/// `field [asc|desc], ...`
+fn parse_fields(tokens: &mut Tokens) -> Result<Vec<(String, bool)>, LiveQueryError> {
+    let mut fields = vec![];
+    loop {
+        let field = match tokens.next() {
+            Some(Token::Word { text, .. }) => text,
+            Some(token) => return Err(LiveQueryError(
+                format!("expected a field, found {}", describe(&token)))),
+            None => return Err(LiveQueryError(String::from("expected a field"))),
+        };
+        let descending = match tokens.peek() {
+            Some(token) if token.is("desc") || token.is("asc") => {
+                let descending = token.is("desc");
+                tokens.next();
+                descending
+            },
+            _ => false,
+        };
+        fields.push((field, descending));
+
+        if tokens.peek() != Some(&Token::Comma) {
+            return Ok(fields);
+        }
+        tokens.next();
+    }
+}
+
+/// Sources up to the next clause, optionally separated by `and`
+fn parse_sources(tokens: &mut Tokens) -> Result<Vec<Source>, LiveQueryError> {
+    let mut sources = vec![];
+    while !at_clause(tokens) {
+        let source = match tokens.next() {
+            Some(token) if token.is("and") => continue,
+            Some(Token::Word { text, quoted: true }) => Source::Folder(text),
+            Some(Token::Word { text, quoted: false }) => {
+                if let Some(tag) = text.strip_prefix("tag:").or_else(|| text.strip_prefix('#')) {
+                    Source::Tag(String::from(tag.trim_start_matches('#')))
+                } else if let Some(dir) = text.strip_prefix("folder:") {
+                    Source::Folder(String::from(dir))
+                } else {
+                    return Err(LiveQueryError(format!("unknown source {:?}", text)));
+                }
+            },
+            Some(token) => return Err(LiveQueryError(
+                format!("unexpected {} in from", describe(&token)))),
+            None => break,
+        };
+        sources.push(source);
+    }
+    if sources.is_empty() {
+        return Err(LiveQueryError(String::from("from needs a tag or folder")));
+    }
+    Ok(sources)
+}
+
+fn parse_or(tokens: &mut Tokens) -> Result<Condition, LiveQueryError> {
+    let mut condition = parse_and(tokens)?;
+    while tokens.peek().is_some_and(|x| x.is("or")) {
+        tokens.next();
+        condition = Condition::Or(Box::new(condition), Box::new(parse_and(tokens)?));
+    }
+    Ok(condition)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Condition, LiveQueryError> {
+    let mut condition = parse_unary(tokens)?;
+    while tokens.peek().is_some_and(|x| x.is("and")) {
+        tokens.next();
+        condition = Condition::And(Box::new(condition), Box::new(parse_unary(tokens)?));
+    }
+    Ok(condition)
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Result<Condition, LiveQueryError> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Condition::Not(Box::new(parse_unary(tokens)?))),
+        Some(token) if token.is("not") =>
+            Ok(Condition::Not(Box::new(parse_unary(tokens)?))),
+        Some(Token::Open) => {
+            let condition = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(condition),
+                _ => Err(LiveQueryError(String::from("missing )"))),
+            }
+        },
+        Some(Token::Word { text: field, quoted: false }) => {
+            let op = match tokens.peek() {
+                Some(Token::Op(op)) => *op,
+                _ => return Ok(Condition::Set(field)),
+            };
+            tokens.next();
+            match tokens.next() {
+                Some(Token::Word { text, .. }) => Ok(Condition::Compare(field, op, text)),
+                _ => Err(LiveQueryError(format!("missing a value to compare {} with", field))),
+            }
+        },
+        Some(token) => Err(LiveQueryError(format!("unexpected {}", describe(&token)))),
+        None => Err(LiveQueryError(String::from("unexpected end of query"))),
+    }
+}
+
+fn lex(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '"' => {
+                chars.next();
+                let text: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Word { text, quoted: true });
+                continue;
+            },
+            '!' | '<' | '>' | '=' => {
+                chars.next();
+                let equals = chars.peek() == Some(&'=');
+                if equals {
+                    chars.next();
+                }
+                tokens.push(match (c, equals) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Op(Op::Ne),
+                    ('<', false) => Token::Op(Op::Lt),
+                    ('<', true) => Token::Op(Op::Le),
+                    ('>', false) => Token::Op(Op::Gt),
+                    ('>', true) => Token::Op(Op::Ge),
+                    _ => Token::Op(Op::Eq),
+                });
+                continue;
+            },
+            _ => {
+                tokens.push(Token::Word { text: lex_word(&mut chars), quoted: false });
+                continue;
+            },
+        };
+        chars.next();
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Read a word ending at whitespace, punctuation or an operator
+fn lex_word(chars: &mut Peekable<Chars>) -> String {
+    let mut text = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || "(),\"!<>=".contains(c) {
+            break;
+        }
+        text.push(c);
+        chars.next();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compared(field: &str, op: Op, value: &str) -> Condition {
+        Condition::Compare(String::from(field), op, String::from(value))
+    }
+
+    #[test]
+    fn parse() {
+        let query = LiveQuery::parse(concat!(
+            "TABLE title, year FROM #paper \"work/papers\" ",
+            "where year >= 2020 and !draft sort year desc, title limit 5")).unwrap();
+        assert_eq!(query, LiveQuery {
+            mode: Mode::Table(vec![String::from("title"), String::from("year")]),
+            from: vec![Source::Tag(String::from("paper")),
+                       Source::Folder(String::from("work/papers"))],
+            filter: Some(Condition::And(
+                Box::new(compared("year", Op::Ge, "2020")),
+                Box::new(Condition::Not(Box::new(Condition::Set(String::from("draft"))))))),
+            sort: vec![(String::from("year"), true), (String::from("title"), false)],
+            limit: Some(5),
+        });
+
+        let query = LiveQuery::parse("tasks from tag:#work and folder:proj").unwrap();
+        assert_eq!(query.mode, Mode::Tasks);
+        assert_eq!(query.from, vec![Source::Tag(String::from("work")),
+                                    Source::Folder(String::from("proj"))]);
+
+        let query = LiveQuery::parse("").unwrap();
+        assert_eq!((query.mode, query.from, query.filter), (Mode::List, vec![], None));
+        assert_eq!(LiveQuery::parse("table from #x").unwrap().mode, Mode::Table(vec![]));
+    }
+
+    #[test]
+    fn conditions() {
+        let filter = |input: &str| LiveQuery::parse(&format!("where {}", input))
+            .unwrap().filter.unwrap();
+
+        // `and` binds stronger than `or`
+        assert_eq!(filter("a or b = 1 and c != \"x y\""), Condition::Or(
+            Box::new(Condition::Set(String::from("a"))),
+            Box::new(Condition::And(
+                Box::new(compared("b", Op::Eq, "1")),
+                Box::new(compared("c", Op::Ne, "x y"))))));
+        assert_eq!(filter("not (a or b)"), Condition::Not(Box::new(Condition::Or(
+            Box::new(Condition::Set(String::from("a"))),
+            Box::new(Condition::Set(String::from("b")))))));
+        assert_eq!(filter("due<today"), compared("due", Op::Lt, "today"));
+        assert_eq!(filter("due <= 2026-10-19"), compared("due", Op::Le, "2026-10-19"));
+    }
+
+    #[test]
+    fn errors() {
+        for input in ["from", "from nowhere", "where", "where (a", "where a =",
+                      "limit ten", "sort", "list list"] {
+            assert!(LiveQuery::parse(input).is_err(), "{:?} should not parse", input);
+        }
+        assert_eq!(LiveQuery::parse("limit x").unwrap_err().to_string(),
+                   "Invalid query: limit \"x\" is not a number");
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(compare("10", Op::Gt, "9"));
+        assert!(compare("2026-10-19", Op::Lt, "2026-10-20"));
+        assert!(compare("Draft", Op::Eq, "draft"));
+        assert!(compare("[a, b]", Op::Eq, "b"));
+        assert!(compare("[a, b]", Op::Ne, "c"));
+        assert!(!compare("[a, b]", Op::Eq, "c"));
+    }
+}
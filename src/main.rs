@@ -1,17 +1,88 @@
-use notes::{self, Notebook, Note};
+use notes::{self, Notebook, Resolved};
+use notes::publish::Profile;
+use notes::listing::SortBy;
+use notes::search::{self, Query};
+use notes::query::Notes;
+use notes::graph::Graph;
+use notes::periodic::Period;
+use notes::check::Checker;
+use notes::board::Board;
+use chrono::{Local, NaiveDate, TimeZone};
 // use notes::compiler::NoteCompiler;
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::Path;
 use std::env;
+use std::io::{self, IsTerminal, Write};
 use std::process;
 use std::fs;
+use std::time::SystemTime;
 
 const DEFAULT_CONFIG: &str = "~/.config/notes.yaml";
 
+/// Ignore patterns used when the config has no `ignore` field
+const DEFAULT_IGNORE: &[&str] = &[
+    "target/", "assets", "attachments", "__layouts", ".git",
+];
+
+/// The commands, used to tell a config path given as the first argument apart
+const COMMANDS: &[&str] = &[
+    "build", "list", "tags", "search", "graph", "tasks", "board", "new",
+    "daily", "weekly", "mv", "check",
+];
+
+const USAGE: &str = "Usage: notes [-c CONFIG | CONFIG] [-p PROFILE] [COMMAND]
+
+The config can also be given as the first argument, as long as it is not the
+name of a command.
+
+Commands:
+    build [FILTERS]     Compile all notes to HTML (default), or only the
+                        notes matching FILTERS
+    list [FILTERS] [--sort title|date] [--json]
+                        List the notes, or export them as JSON lines
+    list --ignored      List the ignored paths and why
+    tags [TAG]          List all tags, or the notes with TAG
+    search [--reindex] QUERY
+                        Search the notes. Supports \"phrases\", prefix*,
+                        AND, OR, NOT, -word, ( ) and the tag:, title:,
+                        path:, body: and meta: filters
+    graph [FILTERS] [--format dot|json|graphml] [--unresolved]
+                        Export the links between the notes matching
+                        FILTERS, optionally with unresolved link targets
+    tasks [FILTERS] [--open|--done] [--overdue] [--note NOTE] [--person NAME]
+                        List the tasks in the notes matching FILTERS
+    board [FILTERS]     Show the notes and tasks grouped by their status
+    board move NOTE COLUMN
+                        Move NOTE to COLUMN by setting its status
+    new [--template TEMPLATE] [--edit] NAME
+                        Create the note NAME from a template, and open it in
+                        $EDITOR with --edit
+    daily [--edit]      Create or find the note for today, and open it in
+                        $EDITOR with --edit
+    weekly [--edit]     The same for the note of the current week
+    mv [--dry-run] NOTE PATH
+                        Move NOTE to PATH, relative to the notebook, and
+                        rewrite the links to it
+    check [FILTERS]     Report broken links, missing files, orphan notes,
+                        duplicate titles and front matter that does not
+                        match the schema. Exits with 1 if any are found
+
+Filters:
+    --tag TAG           Notes with TAG or one of its subtags
+    --under DIR         Notes in DIR or below
+    --since YYYY-MM-DD  Notes modified on or after the date
+    --meta KEY=VALUE    Notes with the front matter value
+    --published         Notes published under the profile";
+
 fn main() {
-    let config_file = env::args().skip(1).next()
-        .unwrap_or(DEFAULT_CONFIG.to_string());
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let config_file = take_option(&mut args, &["-c", "--config"]);
+    let profile = take_option(&mut args, &["-p", "--profile"]);
+    let config_file = config_file
+        .or_else(|| take_config_arg(&mut args))
+        .unwrap_or_else(|| DEFAULT_CONFIG.to_string());
     let config_file = shellexpand::full(&config_file).unwrap();
-    println!("{}", config_file);
     let config = fs::read_to_string(&*config_file).unwrap_or_else(|err| {
         println!("Error opening config file {:?}: {:?}", config_file, err);
         process::exit(1);
@@ -19,10 +90,69 @@ fn main() {
 
     // TODO: This should be able to be done without converting to a Vec
     let config: Vec<String> = config.lines().map(|x| x.to_string()).collect();
-    let config = notes::split_yaml_pairs(&config);
+    let mut config = notes::split_yaml_pairs(&config);
+    if let Some(profile) = profile {
+        config.insert(String::from("profile"), profile);
+    }
+
+    let mut notebook = open_notebook(&config);
+    notebook.scan_and_add();
+
+    let command = args.first().map(|x| x.as_str()).unwrap_or("build");
+    let flags = args.iter().skip(1).map(|x| x.as_str()).collect::<Vec<_>>();
+    match command {
+        "build" if flags.is_empty() => notebook.compile_all().unwrap_or_else(|err| {
+            println!("Error building notes: {}", err);
+            process::exit(1);
+        }),
+        "build" => {
+            let count = select(&notebook, &flags).compile().unwrap_or_else(|err| {
+                println!("Error building notes: {}", err);
+                process::exit(1);
+            });
+            println!("Compiled {} note(s)", count);
+        },
+        "list" => list(&notebook, &flags),
+        "tags" => tags(&notebook, flags.first().copied()),
+        "search" => search(&notebook, &flags),
+        "graph" => graph(&notebook, &flags),
+        "check" => check(&notebook, &flags),
+        "tasks" => tasks(&notebook, &flags),
+        "board" => board(&mut notebook, &flags),
+        "mv" => move_note(&mut notebook, &flags),
+        "new" => new_note(&mut notebook, &flags),
+        "daily" | "weekly" => periodic_note(&mut notebook,
+            Period::from_name(command).unwrap(), &flags),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    process::exit(1);
+}
 
-    println!("{:?}", config);
+/// Remove an option and its value from `args`
+fn take_option(args: &mut Vec<String>, names: &[&str]) -> Option<String> {
+    let i = args.iter().position(|x| names.contains(&x.as_str()))?;
+    if i + 1 >= args.len() {
+        usage();
+    }
+    args.remove(i);
+    Some(args.remove(i))
+}
 
+/// Remove a leading config path, as in `notes my.yaml build`
+fn take_config_arg(args: &mut Vec<String>) -> Option<String> {
+    let first = args.first()?;
+    if first.starts_with('-') || COMMANDS.contains(&first.as_str()) {
+        return None;
+    }
+    Some(args.remove(0))
+}
+
+/// Create the notebook described by the config
+fn open_notebook(config: &HashMap<String, String>) -> Notebook {
     let err_msg = |field| {
         println!("Config field missing: {}", field);
         process::exit(1);
@@ -31,17 +161,378 @@ fn main() {
     let basedir = config.get("path").unwrap_or_else(|| err_msg("path"));
     let outdir = config.get("outdir").unwrap_or_else(|| err_msg("outdir"));
 
-    let mut notebook = Notebook::new(&title, &basedir);
-    notebook.add_ignore(Path::new("target/"));
-    notebook.add_ignore(Path::new("assets"));
-    notebook.add_ignore(Path::new("attachments"));
-    notebook.add_ignore(Path::new("__layouts"));
-    notebook.add_ignore(Path::new("html"));
-    notebook.add_ignore(Path::new(".git"));
+    let mut notebook = Notebook::new(title, basedir);
+    notebook.set_outdir(Path::new(outdir));
 
-    notebook.scan_and_add();
+    match config.get("ignore") {
+        Some(ignore) => notes::split_yaml_list(ignore).iter()
+            .for_each(|pattern| notebook.add_ignore(pattern)),
+        None => DEFAULT_IGNORE.iter()
+            .for_each(|pattern| notebook.add_ignore(pattern)),
+    }
+    if let Some(file) = config.get("search_index") {
+        notebook.set_search_index(Path::new(file));
+    }
+    if let Some(extensions) = config.get("extensions") {
+        notebook.set_extensions(&notes::split_yaml_list(extensions));
+    }
+    notebook.set_use_gitignore(
+        config.get("gitignore").is_some_and(|x| x == "true"));
+
+    let sort = config.get("index_sort").map_or(SortBy::Title, |x| {
+        SortBy::from_name(x).unwrap_or_else(|| {
+            println!("Unknown index sort order: {}", x);
+            process::exit(1);
+        })
+    });
+    notebook.set_index(config.get("index").is_none_or(|x| x != "false"), sort);
+    notebook.set_tag_pages(config.get("tag_pages").is_none_or(|x| x != "false"));
+    notebook.set_site_search(
+        config.get("site_search").is_none_or(|x| x != "false"));
+    notebook.set_graph(
+        config.get("graph").is_none_or(|x| x != "false"),
+        config.get("graph_hops").and_then(|x| x.parse().ok()).unwrap_or(1),
+        config.get("graph_color").map_or("folder", |x| x.as_str()));
+    notebook.set_loose_links(
+        config.get("loose_links").is_some_and(|x| x == "true"));
+    if let Some(dir) = config.get("templates") {
+        notebook.set_templates(Path::new(dir));
+    }
+    if let Some(dir) = config.get("new_note_dir") {
+        notebook.set_new_note_dir(Path::new(dir));
+    }
+    for period in [Period::Daily, Period::Weekly] {
+        let name = period.name();
+        if let Some(pattern) = config.get(&format!("{}_path", name)) {
+            notebook.set_periodic(period, pattern,
+                config.get(&format!("{}_template", name)).map(|x| x.as_str()))
+                .unwrap_or_else(|err| {
+                    println!("Error in config: {}", err);
+                    process::exit(1);
+                });
+        }
+    }
+    notebook.set_calendar(config.get("calendar").is_none_or(|x| x != "false"));
+    notebook.set_agenda(config.get("agenda").is_none_or(|x| x != "false"));
+    notebook.set_ical(config.get("ical").is_none_or(|x| x != "false"));
+    notebook.set_board(
+        config.get("board").is_none_or(|x| x != "false"),
+        config.get("board_field").map_or("status", |x| x.as_str()),
+        &config.get("board_columns").map_or_else(Vec::new, |x| notes::split_yaml_list(x)));
+    if let Some(file) = config.get("schema") {
+        let strict = config.get("strict_schema").is_some_and(|x| x == "true");
+        notebook.set_schema(Path::new(file), strict).unwrap_or_else(|err| {
+            println!("Error reading schema: {}", err);
+            process::exit(1);
+        });
+    }
+    notebook.set_heading_anchors(
+        config.get("heading_anchors").is_some_and(|x| x == "true"));
+    notebook.set_redirects(config.get("redirects").is_none_or(|x| x != "false"));
+
+    // Profiles can be defined as `profile.<name>: [flags to exclude]`
+    if let Some(name) = config.get("profile") {
+        let profile = match config.get(&format!("profile.{}", name)) {
+            Some(exclude) => Profile {
+                name: name.clone(),
+                exclude: notes::split_yaml_list(exclude),
+            },
+            None => Profile::builtin(name).unwrap_or_else(|| {
+                println!("Unknown publish profile: {}", name);
+                process::exit(1);
+            }),
+        };
+        notebook.set_profile(profile);
+    }
+
+    notebook
+}
+
+/// `notes list [--ignored]`
+fn list(notebook: &Notebook, flags: &[&str]) {
+    if flags.contains(&"--ignored") {
+        for ignored in notebook.ignored() {
+            println!("{}\t{}\t{}", ignored.path.display(), ignored.source,
+                     ignored.pattern);
+        }
+        return;
+    }
+
+    let mut notes = select(notebook, flags);
+    if let Some(sort) = option(flags, "--sort") {
+        notes = notes.sorted_by(SortBy::from_name(sort).unwrap_or_else(|| usage()));
+    }
+    if flags.contains(&"--json") {
+        notes.export(&mut io::stdout()).unwrap();
+    } else {
+        notes.print();
+    }
+}
+
+/// `notes tags [TAG]`
+fn tags(notebook: &Notebook, tag: Option<&str>) {
+    match tag {
+        Some(tag) => notebook.notes().with_tag(tag).print(),
+        None => {
+            for (tag, count) in notebook.tags() {
+                println!("{}\t{}", tag, count);
+            }
+        },
+    }
+}
+
+/// `notes graph [FILTERS] [--format dot|json|graphml] [--unresolved]`
+fn graph(notebook: &Notebook, flags: &[&str]) {
+    let graph = Graph::build(notebook, select(notebook, flags),
+                             flags.contains(&"--unresolved"));
+
+    match option(flags, "--format").unwrap_or("dot") {
+        "dot" => print!("{}", graph.to_dot()),
+        "json" => print!("{}", graph.to_json()),
+        "graphml" => print!("{}", graph.to_graphml()),
+        _ => usage(),
+    }
+}
+
+/// `notes check [FILTERS]`
+fn check(notebook: &Notebook, flags: &[&str]) {
+    let problems = Checker::new(notebook).check(select(notebook, flags));
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    if !problems.is_empty() {
+        println!("{} problem(s) found", problems.len());
+        process::exit(1);
+    }
+}
+
+/// `notes tasks [FILTERS] [--open|--done] [--overdue] [--note NOTE]
+/// [--person NAME]`
+fn tasks(notebook: &Notebook, flags: &[&str]) {
+    let mut notes = select(notebook, flags);
+    if let Some(note) = option(flags, "--note") {
+        let id = note_arg(notebook, note);
+        notes = notes.matching(move |note| note.id() == id);
+    }
 
-    notebook.compile_all().unwrap();
+    let today = Local::now().date_naive();
+    let person = option(flags, "--person").map(|x| x.trim_start_matches('@'));
+    let tasks = notes.tasks().into_iter()
+        .filter(|task| !flags.contains(&"--open") || !task.done)
+        .filter(|task| !flags.contains(&"--done") || task.done)
+        .filter(|task| !flags.contains(&"--overdue") || task.is_overdue(today))
+        .filter(|task| person.is_none_or(|x| task.people.iter().any(|p| p == x)));
+
+    for task in tasks {
+        println!("{}:{}: [{}] {}", task.note.path.display(), task.position.0,
+                 if task.done { 'x' } else { ' ' }, task.text);
+    }
+}
+
+/// `notes board [FILTERS]` and `notes board move NOTE COLUMN`
+fn board(notebook: &mut Notebook, flags: &[&str]) {
+    let (note, column) = match flags {
+        ["move", note, column] => (*note, *column),
+        ["move", ..] => usage(),
+        _ => return Board::build(notebook, select(notebook, flags)).print(),
+    };
+
+    let id = note_arg(notebook, note);
+    let field = String::from(notebook.board_field());
+    let old = notebook.get(&id).unwrap().meta(&field);
+    notebook.set_meta(&id, &field, column).unwrap_or_else(|err| {
+        println!("Error moving {}: {}", id, err);
+        process::exit(1);
+    });
+    match old {
+        Some(old) => println!("Moved {} from {} to {}", id, old, column),
+        None => println!("Moved {} to {}", id, column),
+    }
+}
+
+/// `notes new [--template TEMPLATE] [--edit] NAME`
+fn new_note(notebook: &mut Notebook, flags: &[&str]) {
+    let template = option(flags, "--template");
+    let name: Vec<&str> = flags.iter().enumerate()
+        .filter(|(i, x)| **x != "--edit" && **x != "--template"
+                && (*i == 0 || flags[i - 1] != "--template"))
+        .map(|(_, x)| *x)
+        .collect();
+    if name.is_empty() {
+        usage();
+    }
+
+    let note = notebook.new_note(&name.join(" "), template, prompt)
+        .unwrap_or_else(|err| {
+            println!("Error creating note: {}", err);
+            process::exit(1);
+        });
+    println!("Created {}", note.path.display());
+
+    if flags.contains(&"--edit") {
+        edit(&note.path);
+    }
+}
+
+/// `notes daily [--edit]` and `notes weekly [--edit]`
+fn periodic_note(notebook: &mut Notebook, period: Period, flags: &[&str]) {
+    let today = Local::now().date_naive();
+    let (note, created) = notebook.periodic_note(period, today, prompt)
+        .unwrap_or_else(|err| {
+            println!("Error creating note: {}", err);
+            process::exit(1);
+        });
+    println!("{} {}", if created { "Created" } else { "Found" },
+             note.path.display());
+
+    if flags.contains(&"--edit") {
+        edit(&note.path);
+    }
+}
+
+/// Ask a question for a template on the terminal
+fn prompt(question: &str) -> String {
+    print!("{}: ", question);
+    io::stdout().flush().unwrap();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).unwrap();
+    String::from(answer.trim())
+}
+
+/// Open `path` in `$EDITOR`
+fn edit(path: &Path) {
+    let editor = env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
+    let mut editor = editor.split_whitespace();
+    let status = process::Command::new(editor.next().unwrap_or("vi"))
+        .args(editor)
+        .arg(path)
+        .status();
+    if let Err(err) = status {
+        println!("Error starting the editor: {}", err);
+        process::exit(1);
+    }
+}
+
+/// `notes mv [--dry-run] NOTE PATH`
+fn move_note(notebook: &mut Notebook, flags: &[&str]) {
+    let dry_run = flags.contains(&"--dry-run");
+    let (note, to) = match flags.iter().filter(|x| **x != "--dry-run")
+                                .collect::<Vec<_>>()[..] {
+        [note, to] => (*note, *to),
+        _ => usage(),
+    };
+
+    let id = note_arg(notebook, note);
+    let moved = notebook.move_note(&id, Path::new(to), dry_run)
+        .unwrap_or_else(|err| {
+            println!("Error moving {}: {}", id, err);
+            process::exit(1);
+        });
+
+    let relative = |path: &Path| path.strip_prefix(notebook.basedir())
+        .unwrap_or(path).display().to_string();
+    println!("{} {} -> {}", if dry_run { "Would move" } else { "Moved" },
+             relative(&moved.from), relative(&moved.to));
+    for edit in &moved.edits {
+        println!("  {}:{}: {} -> {}", relative(&edit.path), edit.line,
+                 edit.old, edit.new);
+    }
+    println!("{} link(s) in {} note(s) {}", moved.edits.len(),
+             moved.notes_changed(),
+             if dry_run { "would be rewritten" } else { "rewritten" });
+    for path in &moved.skipped {
+        println!("Warning: links in {} were not rewritten", relative(path));
+    }
+}
+
+/// Value of the option `name` in `flags`
+fn option<'a>(flags: &[&'a str], name: &str) -> Option<&'a str> {
+    let i = flags.iter().position(|x| *x == name)?;
+    Some(flags.get(i + 1).copied().unwrap_or_else(|| usage()))
+}
+
+/// Id of the note given on the command line, as a path relative to the
+/// notebook or as a link to the note
+fn note_arg(notebook: &Notebook, note: &str) -> String {
+    let id = notebook.note_id(&notebook.basedir().join(note));
+    match (notebook.get(&id), notebook.resolve(note)) {
+        (Some(_), _) => id,
+        (None, Resolved::Found(note)) => String::from(note.id()),
+        (None, Resolved::Ambiguous(candidates)) => {
+            println!("{} matches {}", note, candidates.join(", "));
+            process::exit(1);
+        },
+        (None, Resolved::Missing) => {
+            println!("Unknown note: {}", note);
+            process::exit(1);
+        },
+    }
+}
+
+/// Select notes using the filter options in `flags`
+fn select<'a>(notebook: &'a Notebook, flags: &[&str]) -> Notes<'a> {
+    let mut notes = notebook.notes();
+
+    for (i, flag) in flags.iter().enumerate() {
+        let value = || flags.get(i + 1).copied().unwrap_or_else(|| usage());
+        notes = match *flag {
+            "--tag" => notes.with_tag(value()),
+            "--under" => notes.under(value()),
+            "--since" => notes.modified_since(parse_date(value())),
+            "--meta" => match value().split_once('=') {
+                Some((key, val)) => notes.with_meta(key, val),
+                None => usage(),
+            },
+            "--published" => notes.published(),
+            _ => notes,
+        };
+    }
+    notes
+}
+
+/// Start of a `YYYY-MM-DD` day in local time
+fn parse_date(date: &str) -> SystemTime {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .unwrap_or_else(|_| {
+            println!("Invalid date {:?}, expected YYYY-MM-DD", date);
+            process::exit(1);
+        });
+    let time = Local.from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        .earliest().unwrap();
+    SystemTime::from(time)
+}
+
+/// `notes search [--reindex] QUERY`
+fn search(notebook: &Notebook, flags: &[&str]) {
+    let rebuild = flags.contains(&"--reindex");
+    let query: Vec<&str> = flags.iter().copied()
+        .filter(|x| *x != "--reindex")
+        .collect();
+    if query.is_empty() {
+        usage();
+    }
+
+    let query = Query::parse(&query.join(" ")).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(1);
+    });
+    let index = notebook.search_index(rebuild).unwrap_or_else(|err| {
+        println!("Error updating the search index: {}", err);
+        process::exit(1);
+    });
+
+    let (before, after) = match io::stdout().is_terminal() {
+        true => ("\x1b[1;33m", "\x1b[0m"),
+        false => ("**", "**"),
+    };
+    for hit in index.search(&query) {
+        let note = notebook.get(&hit.id).unwrap();
+        let text = search::plain_text(&note.read());
+
+        println!("{} - {} ({:.2})", hit.id, note.title(), hit.score);
+        println!("    {}", search::snippet(&text, &query, 100, before, after));
+    }
 }
 
 /*
@@ -12,6 +12,8 @@ struct Flashcard {
 
 enum FlashcardStyle {
     Oneline,
+    // TODO: Batch style is not detected yet
+    #[allow(dead_code)]
     Batch,
     Regular,
 }
@@ -45,6 +47,12 @@ r#"<div class="flashcard">
     }
 }
 
+impl Default for FlashcardParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser for FlashcardParser {
     fn parse(&self, content: &str) -> String {
 
@@ -76,7 +84,7 @@ impl Parser for FlashcardParser {
                         active = None;
 
                         // Return formatted flashcard
-                        return self.format_flashcard(&card);
+                        return self.format_flashcard(card);
                     }
 
                     String::new()
@@ -105,7 +113,7 @@ impl Parser for FlashcardParser {
                                         card.tags.push_str(tags.as_str());
                                     }
 
-                                    self.format_flashcard(&card)
+                                    self.format_flashcard(card)
                                 },
                                 FlashcardStyle::Regular => {
                                     println!("Regular question: {:?}", cap);
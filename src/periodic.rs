@@ -0,0 +1,289 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+
+/// File name of the calendar page, in the root of the outdir
+pub const CALENDAR_PAGE: &str = "calendar.html";
+
+/// Kind of periodic note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Daily,
+    Weekly,
+}
+
+impl Period {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "daily" => Some(Period::Daily),
+            "weekly" => Some(Period::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Daily => "daily",
+            Period::Weekly => "weekly",
+        }
+    }
+
+    /// First day of the period containing `date`
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Daily => date,
+            Period::Weekly => date - Duration::days(
+                date.weekday().num_days_from_monday() as i64),
+        }
+    }
+}
+
+/// Where the notes of a period live
+pub struct PeriodicNotes {
+    pub period: Period,
+    /// Note id of the note for a date, as a `strftime` format, e.g.
+    /// `journal/%Y-%m-%d` or `journal/%G-W%V`. Set with
+    /// `Notebook::set_periodic`, which checks that it is a valid format.
+    pub(crate) pattern: String,
+    /// Template for new notes. Without one the template named after the
+    /// period is used if there is one.
+    pub template: Option<String>,
+}
+
+impl PeriodicNotes {
+    pub fn new(period: Period) -> Self {
+        let pattern = match period {
+            Period::Daily => "daily/%Y-%m-%d",
+            Period::Weekly => "weekly/%G-W%V",
+        };
+        Self { period, pattern: String::from(pattern), template: None }
+    }
+
+    /// Note id for the period containing `date`
+    pub fn id(&self, date: NaiveDate) -> String {
+        self.period.start(date).format(&self.pattern).to_string()
+    }
+
+    /// Start of the period of the note with `id`, if it is a periodic note
+    pub fn date(&self, id: &str) -> Option<NaiveDate> {
+        // Patterns with a week but no day, like `%G-W%V`, get the Monday
+        let date = NaiveDate::parse_from_str(id, &self.pattern)
+            .or_else(|_| NaiveDate::parse_from_str(&format!("{} 1", id),
+                                                   &format!("{} %u", self.pattern)))
+            .ok()?;
+
+        // Parsing skips leading zeros and whitespace, only accept the exact id
+        match self.id(date) == id {
+            true => Some(self.period.start(date)),
+            false => None,
+        }
+    }
+
+    /// All periodic notes published in `notebook`, by date
+    pub fn notes<'a>(&self, notebook: &'a Notebook) -> BTreeMap<NaiveDate, &'a Note> {
+        notebook.notes().published()
+            .filter_map(|note| Some((self.date(note.id())?, note)))
+            .collect()
+    }
+
+    /// The `{periodic}` template slot for `note`: links to the previous and
+    /// next note of the period in `notes`, from `PeriodicNotes::notes`, and
+    /// to the calendar. Empty for other notes.
+    pub fn navigation(&self, note: &Note, notes: &BTreeMap<NaiveDate, &Note>,
+                      root: &str) -> Option<String>
+    {
+        let date = self.date(note.id())?;
+        let link = |note: &Note, text: String| format!(
+            "<a href=\"{}{}.html\">{}</a>", root, escape(note.id()), text);
+
+        let previous = notes.range(..date).next_back()
+            .map(|(_, x)| link(x, format!("&larr; {}", escape(x.title()))));
+        let next = notes.range(date.succ_opt()?..).next()
+            .map(|(_, x)| link(x, format!("{} &rarr;", escape(x.title()))));
+
+        Some(format!(concat!(
+            "<nav class=\"periodic\">\n",
+            "<span class=\"previous\">{}</span>\n",
+            "<a class=\"calendar\" href=\"{}{}\">Calendar</a>\n",
+            "<span class=\"next\">{}</span>\n",
+            "</nav>\n"),
+            previous.unwrap_or_default(), root, CALENDAR_PAGE,
+            next.unwrap_or_default()))
+    }
+}
+
+/// The published daily and weekly notes by date, collected once per build
+/// for the navigation between them
+pub struct PeriodicIndex<'a> {
+    periods: Vec<(&'a PeriodicNotes, BTreeMap<NaiveDate, &'a Note>)>,
+}
+
+impl<'a> PeriodicIndex<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        let periods = [Period::Daily, Period::Weekly].iter()
+            .map(|x| notebook.periodic(*x))
+            .map(|periodic| (periodic, periodic.notes(notebook)))
+            .collect();
+        Self { periods }
+    }
+
+    /// The `{periodic}` template slot for `note`, see
+    /// `PeriodicNotes::navigation`
+    pub fn navigation(&self, note: &Note, root: &str) -> Option<String> {
+        self.periods.iter()
+            .find_map(|(periodic, notes)| periodic.navigation(note, notes, root))
+    }
+}
+
+/// Writes a calendar with a month grid for every month with daily or weekly
+/// notes, linking to the notes
+pub struct Calendar<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> Calendar<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// Write the calendar, also without periodic notes so an old one does
+    /// not stay around
+    pub fn write_all(&self) -> io::Result<()> {
+        let daily = self.notebook.periodic(Period::Daily).notes(self.notebook);
+        let weekly = self.notebook.periodic(Period::Weekly).notes(self.notebook);
+
+        // Weeks are shown in the months they overlap
+        let months: BTreeSet<(i32, u32)> = daily.keys()
+            .copied()
+            .chain(weekly.keys().flat_map(|&x| [x, x + Duration::days(6)]))
+            .map(|x| (x.year(), x.month()))
+            .collect();
+
+        let mut html = String::from("<h1>Calendar</h1>\n");
+        if months.is_empty() {
+            html.push_str("<p>No daily or weekly notes.</p>\n");
+        }
+        for (year, month) in months.into_iter().rev() {
+            html.push_str(&self.render_month(year, month, &daily, &weekly));
+        }
+
+        let outfile = self.notebook.outdir().join(CALENDAR_PAGE);
+        println!("Writing to {}", outfile.display());
+        fs::create_dir_all(self.notebook.outdir())?;
+        let page = self.notebook.compiler()
+            .to_page("Calendar", &html, 0, &[], self.notebook);
+        fs::File::create(&outfile)?.write_all(page.as_bytes())
+    }
+
+    /// Month grid with a row per week, starting with the week number
+    fn render_month(&self, year: i32, month: u32,
+                    daily: &BTreeMap<NaiveDate, &Note>,
+                    weekly: &BTreeMap<NaiveDate, &Note>) -> String {
+        let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+        let link = |note: &Note, text: String| format!(
+            "<a href=\"{}.html\" title=\"{}\">{}</a>",
+            escape(note.id()), escape(note.title()), text);
+
+        let mut html = format!(concat!(
+            "<table class=\"calendar\">\n",
+            "<caption>{}</caption>\n",
+            "<tr><th>Wk</th><th>Mo</th><th>Tu</th><th>We</th><th>Th</th>",
+            "<th>Fr</th><th>Sa</th><th>Su</th></tr>\n"),
+            first.format("%B %Y"));
+
+        let mut week = Period::Weekly.start(first);
+        while week.year() < year || (week.year() == year && week.month() <= month) {
+            let number = week.iso_week().week().to_string();
+            html.push_str(&format!("<tr><th>{}</th>", match weekly.get(&week) {
+                Some(note) => link(note, number),
+                None => number,
+            }));
+
+            for day in week.iter_days().take(7) {
+                let text = day.day().to_string();
+                html.push_str(&match (day.month() == month, daily.get(&day)) {
+                    (false, _) => String::from("<td></td>"),
+                    (true, Some(note)) => format!("<td class=\"note\">{}</td>",
+                                                  link(note, text)),
+                    (true, None) if day.weekday() == Weekday::Sun
+                                    || day.weekday() == Weekday::Sat =>
+                        format!("<td class=\"weekend\">{}</td>", text),
+                    (true, None) => format!("<td>{}</td>", text),
+                });
+            }
+            html.push_str("</tr>\n");
+            week += Duration::days(7);
+        }
+
+        html.push_str("</table>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn ids() {
+        let daily = PeriodicNotes::new(Period::Daily);
+        assert_eq!(daily.id(day(2026, 3, 5)), "daily/2026-03-05");
+        assert_eq!(daily.date("daily/2026-03-05"), Some(day(2026, 3, 5)));
+        assert_eq!(daily.date("daily/2026-3-5"), None);
+        assert_eq!(daily.date("journal/2026-03-05"), None);
+
+        // Weeks start on Monday, 2027-01-01 is in week 53 of 2026
+        let weekly = PeriodicNotes::new(Period::Weekly);
+        assert_eq!(weekly.id(day(2027, 1, 1)), "weekly/2026-W53");
+        assert_eq!(weekly.date("weekly/2026-W53"), Some(day(2026, 12, 28)));
+        assert_eq!(weekly.date("weekly/2026-W54"), None);
+    }
+
+    #[test]
+    fn patterns() {
+        let mut notebook = Notebook::new("Test", "/notes");
+        notebook.set_periodic(Period::Daily, "journal/%Y/%m-%d", None).unwrap();
+        assert_eq!(notebook.periodic(Period::Daily).id(day(2026, 10, 19)),
+                   "journal/2026/10-19");
+
+        assert!(notebook.set_periodic(Period::Daily, "journal/%Q", None).is_err());
+        assert_eq!(notebook.periodic(Period::Daily).id(day(2026, 10, 19)),
+                   "journal/2026/10-19");
+    }
+
+    #[test]
+    fn navigation() {
+        let notebook = crate::tests::notebook(&[
+            ("daily/2026-01-01.md", ""),
+            ("daily/2026-01-03.md", ""),
+            ("daily/2026-01-04.md", "---\ndraft: true\n---\n"),
+            ("daily/2026-01-05.md", ""),
+            ("weekly/2026-W02.md", ""),
+            ("other.md", ""),
+        ]);
+        let index = PeriodicIndex::new(&notebook);
+        let nav = |id| index.navigation(notebook.get(id).unwrap(), "../");
+
+        assert_eq!(nav("daily/2026-01-03").unwrap(), concat!(
+            "<nav class=\"periodic\">\n",
+            "<span class=\"previous\"><a href=\"../daily/2026-01-01.html\">",
+            "&larr; 2026-01-01</a></span>\n",
+            "<a class=\"calendar\" href=\"../calendar.html\">Calendar</a>\n",
+            "<span class=\"next\"><a href=\"../daily/2026-01-05.html\">",
+            "2026-01-05 &rarr;</a></span>\n",
+            "</nav>\n"));
+        assert!(nav("daily/2026-01-05").unwrap()
+                .contains("<span class=\"next\"></span>"));
+        assert!(nav("weekly/2026-W02").unwrap()
+                .contains("<span class=\"previous\"></span>"));
+        assert_eq!(nav("other"), None);
+    }
+}
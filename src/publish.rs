@@ -0,0 +1,96 @@
+use crate::Note;
+
+/// A publish profile decides which notes get compiled
+///
+/// Notes with any of the `exclude` flags set to true in their front matter
+/// (e.g. `draft: true`) are left out.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub exclude: Vec<String>,
+}
+
+impl Profile {
+    pub fn new(name: &str, exclude: &[&str]) -> Self {
+        Self {
+            name: String::from(name),
+            exclude: exclude.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    /// Builtin profiles: `public` leaves out drafts and private notes,
+    /// `personal` only leaves out drafts and `all` publishes everything.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "public" => Some(Self::new(name, &["draft", "private"])),
+            "personal" => Some(Self::new(name, &["draft"])),
+            "all" => Some(Self::new(name, &[])),
+            _ => None,
+        }
+    }
+
+    /// Check whether `note` is published under this profile
+    pub fn includes(&self, note: &Note) -> bool {
+        !self.exclude.iter().any(|flag| note.flag(flag))
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::builtin("public").unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn builtin() {
+        let notebook = notebook(&[
+            ("plain.md", "Text"),
+            ("draft.md", "---\ndraft: true\n---\n"),
+            ("private.md", "---\nprivate: yes\n---\n"),
+            ("done.md", "---\ndraft: false\n---\n"),
+        ]);
+        let published = |profile: &str| -> Vec<&str> {
+            let profile = Profile::builtin(profile).unwrap();
+            let mut ids: Vec<&str> = notebook.note_ids().into_iter()
+                .filter(|id| profile.includes(notebook.get(id).unwrap()))
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+
+        assert_eq!(published("public"), vec!["done", "plain"]);
+        assert_eq!(published("personal"), vec!["done", "plain", "private"]);
+        assert_eq!(published("all"), vec!["done", "draft", "plain", "private"]);
+        assert!(Profile::builtin("other").is_none());
+    }
+
+    #[test]
+    fn build() {
+        let mut notebook = notebook(&[
+            ("index.md", "See [[draft]], [[secret|the secret]] and [[plain]]"),
+            ("plain.md", "Text"),
+            ("draft.md", "---\ndraft: true\n---\nUnfinished"),
+            ("secret.md", "---\nhidden: true\n---\nSecret"),
+        ]);
+        notebook.set_profile(Profile::new("site", &["draft", "hidden"]));
+        // Output of notes that are no longer published is removed
+        let outdir = notebook.outdir();
+        std::fs::create_dir_all(&outdir).unwrap();
+        std::fs::write(outdir.join("draft.html"), "old").unwrap();
+        notebook.compile_all().unwrap();
+
+        assert!(outdir.join("plain.html").exists());
+        assert!(!outdir.join("draft.html").exists());
+        assert!(!outdir.join("secret.html").exists());
+
+        let index = notebook.get("index").unwrap();
+        assert_eq!(notebook.compiler().to_html(index, &notebook),
+                   "<p>See draft, the secret and \
+                    <a href=\"plain.html\" title=\"wikilink\">plain</a></p>\n");
+    }
+}
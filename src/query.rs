@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use crate::{Note, Notebook};
+use crate::json;
+use crate::listing::SortBy;
+use crate::periodic::PeriodicIndex;
+use crate::tags;
+use crate::tasks::Task;
+
+/// Composable query over the notes of a notebook
+///
+/// Created with `Notebook::notes`, e.g.
+/// `notebook.notes().with_tag("course").under("physics").sorted_by_title()`.
+/// This is an iterator over `&Note`, with terminal operations to compile,
+/// export or print the selected notes.
+pub struct Notes<'a> {
+    notebook: &'a Notebook,
+    iter: Box<dyn Iterator<Item = &'a Note> + 'a>,
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = &'a Note;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl<'a> Notes<'a> {
+    /// All notes in `notebook`, ordered by id
+    pub fn new(notebook: &'a Notebook) -> Self {
+        let mut notes: Vec<&Note> = notebook.notes.values().collect();
+        notes.sort_by(|a, b| a.id().cmp(b.id()));
+
+        Self {
+            notebook,
+            iter: Box::new(notes.into_iter()),
+        }
+    }
+
+    /// Keep the notes for which `predicate` returns true
+    pub fn matching<F>(self, predicate: F) -> Self
+        where F: Fn(&Note) -> bool + 'a
+    {
+        Self {
+            notebook: self.notebook,
+            iter: Box::new(self.iter.filter(move |note| predicate(note))),
+        }
+    }
+
+    /// Notes with `tag` or one of its subtags, so `course` also finds notes
+    /// tagged `course/physics`
+    pub fn with_tag(self, tag: &str) -> Self {
+        let tag = String::from(tag);
+        self.matching(move |note| note.has_tag(&tag))
+    }
+
+    /// Notes with any of the given tags
+    pub fn with_any_tag(self, any: &[String]) -> Self {
+        let any = any.to_vec();
+        self.matching(move |note| {
+            note.tags().iter().any(|x| any.iter().any(|tag| tags::matches(x, tag)))
+        })
+    }
+
+    /// Notes in directory `dir` (relative to the basedir) or below
+    pub fn under(self, dir: &str) -> Self {
+        let prefix = format!("{}/", dir.trim_matches('/'));
+        self.matching(move |note| prefix == "/" || note.id().starts_with(&prefix))
+    }
+
+    /// Notes with front matter `key` set to `value`
+    pub fn with_meta(self, key: &str, value: &str) -> Self {
+        let (key, value) = (String::from(key), String::from(value));
+        self.matching(move |note| note.meta(&key).as_deref() == Some(&value))
+    }
+
+    /// Notes whose file changed at or after `time`
+    pub fn modified_since(self, time: SystemTime) -> Self {
+        self.matching(move |note| note.modified().is_some_and(|x| x >= time))
+    }
+
+    /// Notes published under the current profile
+    pub fn published(self) -> Self {
+        let notebook = self.notebook;
+        self.matching(move |note| notebook.is_published(note))
+    }
+
+    pub fn sorted_by(self, sort: SortBy) -> Self {
+        let mut notes: Vec<(&str, &Note)> = self.iter
+            .map(|note| (note.id(), note))
+            .collect();
+        sort.sort(&mut notes);
+
+        Self {
+            notebook: self.notebook,
+            iter: Box::new(notes.into_iter().map(|(_, note)| note)),
+        }
+    }
+
+    pub fn sorted_by_title(self) -> Self {
+        self.sorted_by(SortBy::Title)
+    }
+
+    /// Newest first by the `date` metadata
+    pub fn sorted_by_date(self) -> Self {
+        self.sorted_by(SortBy::Date)
+    }
+
+    /// Compile the selected notes to HTML. Notes that are not published
+    /// under the current profile are skipped, and their old output removed.
+    /// Returns the number of notes compiled.
+    pub fn compile(self) -> io::Result<usize> {
+        let notebook = self.notebook;
+        let periodic = PeriodicIndex::new(notebook);
+        let mut count = 0;
+        for note in self {
+            if notebook.publish_note(note, &periodic)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Write the id, title, path, tags and metadata of the selected notes as
+    /// JSON, one note per line.
+    pub fn export<W: Write>(self, out: &mut W) -> io::Result<()> {
+        for note in self {
+            let mut meta: Vec<(String, String)> = note.metadata.borrow()
+                .iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            meta.sort();
+            let meta: Vec<String> = meta.iter()
+                .map(|(k, v)| format!("{}:{}", json::string(k), json::string(v)))
+                .collect();
+
+            writeln!(out, "{{\"id\":{},\"title\":{},\"path\":{},\"tags\":{},\
+                           \"meta\":{{{}}}}}",
+                json::string(note.id()),
+                json::string(note.title()),
+                json::string(&note.path.to_string_lossy()),
+                json::string_array(note.tags()),
+                meta.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// The tasks in the selected notes, ordered by note and position
+    pub fn tasks(self) -> Vec<Task<'a>> {
+        let options = self.notebook.compiler().parse_options;
+        self.flat_map(|note| Task::find(note, options)).collect()
+    }
+
+    /// Print the id and title of the selected notes
+    pub fn print(self) {
+        for note in self {
+            println!("{}\t{}", note.id(), note.title());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+    use std::fs;
+    use std::time::Duration;
+
+    fn ids(notes: Notes<'_>) -> Vec<&str> {
+        notes.map(|note| note.id()).collect()
+    }
+
+    fn sample() -> Notebook {
+        notebook(&[
+            ("physics/waves.md", "---\ntitle: Waves\ntags: [course/physics]\n\
+                                  date: 2021-03-01\n---\nText"),
+            ("physics/optics.md", "---\ntitle: optics\ndate: 2022-01-10\n---\n#course"),
+            ("math.md", "---\ntitle: Algebra\ntags: [course]\nstatus: done\n---\n"),
+            ("diary.md", "---\ntitle: Diary\ndraft: true\n---\n#personal"),
+        ])
+    }
+
+    #[test]
+    fn filters() {
+        let notebook = sample();
+
+        assert_eq!(ids(notebook.notes()),
+                   vec!["diary", "math", "physics/optics", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().with_tag("course")),
+                   vec!["math", "physics/optics", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().with_tag("course/physics")),
+                   vec!["physics/waves"]);
+        assert_eq!(ids(notebook.notes().with_tag("cour")), Vec::<&str>::new());
+        assert_eq!(ids(notebook.notes().with_any_tag(&["personal".into(),
+                                                       "course/physics".into()])),
+                   vec!["diary", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().under("physics")),
+                   vec!["physics/optics", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().under("/physics/")),
+                   vec!["physics/optics", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().under("phys")), Vec::<&str>::new());
+        assert_eq!(ids(notebook.notes().with_meta("status", "done")), vec!["math"]);
+        assert_eq!(ids(notebook.notes().published()),
+                   vec!["math", "physics/optics", "physics/waves"]);
+        assert_eq!(ids(notebook.notes().with_tag("course").under("physics")
+                       .with_meta("date", "2021-03-01")),
+                   vec!["physics/waves"]);
+    }
+
+    #[test]
+    fn sorted_by() {
+        let notebook = sample();
+
+        // Case insensitive
+        assert_eq!(ids(notebook.notes().sorted_by_title()),
+                   vec!["math", "diary", "physics/optics", "physics/waves"]);
+        // Newest first, undated notes last by title
+        assert_eq!(ids(notebook.notes().sorted_by_date()),
+                   vec!["physics/optics", "physics/waves", "math", "diary"]);
+    }
+
+    #[test]
+    fn modified_since() {
+        let notebook = sample();
+        let now = SystemTime::now();
+
+        assert_eq!(notebook.notes().modified_since(now - Duration::from_secs(3600))
+                   .count(), 4);
+        assert_eq!(notebook.notes().modified_since(now + Duration::from_secs(3600))
+                   .count(), 0);
+    }
+
+    #[test]
+    fn export() {
+        let notebook = notebook(&[
+            ("a/quote.md", "---\ntitle: Say \"hi\"\ntags: [x, y]\nkey: a\\b\n---\n"),
+        ]);
+        let mut out = Vec::new();
+        notebook.notes().export(&mut out).unwrap();
+
+        let path = notebook.basedir().join("a/quote.md");
+        assert_eq!(String::from_utf8(out).unwrap(), format!(
+            "{{\"id\":\"a/quote\",\"title\":\"Say \\\"hi\\\"\",\"path\":{},\
+             \"tags\":[\"x\",\"y\"],\"meta\":{{\"key\":\"a\\\\b\",\
+             \"tags\":\"[x, y]\"}}}}\n",
+            json::string(&path.to_string_lossy())));
+    }
+
+    #[test]
+    fn compile() {
+        let notebook = sample();
+        let stale = notebook.outdir().join("diary.html");
+        fs::create_dir_all(notebook.outdir()).unwrap();
+        fs::write(&stale, "old").unwrap();
+
+        assert_eq!(notebook.notes().with_tag("course").compile().unwrap(), 3);
+        assert!(notebook.outdir().join("physics/waves.html").exists());
+        assert!(stale.exists());
+
+        assert_eq!(notebook.notes().with_tag("personal").compile().unwrap(), 0);
+        assert!(!stale.exists());
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+use crate::listing::{join, parent};
+
+/// Writes a page for every alias and previous name of a note, which sends
+/// the browser on to the note
+///
+/// Aliases are names in the directory of the note, so `ML` on
+/// `cs/machine-learning` redirects from `cs/ML.html`. Previous names come from
+/// the `redirect_from` front matter list and are ids, relative to the basedir.
+/// Names of other notes, of generated pages and names outside the outdir are
+/// skipped with a warning.
+pub struct Redirects<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> Redirects<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// {Old id, note}, for all published notes
+    fn redirects(&self) -> Vec<(String, &'a Note)> {
+        let mut output = vec![];
+        for note in self.notebook.notes().published() {
+            let aliases = note.aliases().iter()
+                .map(|alias| join(parent(note.id()), alias.trim_matches('/')));
+            let previous = note.meta("redirect_from")
+                .map_or_else(Vec::new, |x| crate::split_yaml_list(&x))
+                .into_iter()
+                .map(|id| String::from(id.trim_matches('/')));
+
+            for id in aliases.chain(previous) {
+                // Never write outside the outdir, or replace the page of an
+                // existing note or a generated page
+                if let Some(problem) = self.problem(&id) {
+                    println!("Warning: {} redirects from {}, {}", note.id(), id,
+                             problem);
+                    continue;
+                }
+                match self.notebook.get(&id) {
+                    Some(other) if other.id() != note.id() => println!(
+                        "Warning: {} redirects from {}, which is a note itself",
+                        note.id(), id),
+                    Some(_) => (),
+                    None => output.push((id, note)),
+                }
+            }
+        }
+        output
+    }
+
+    /// Why no redirect page can be written for `id`, if it is not a valid
+    /// name in the outdir or a generated page
+    fn problem(&self, id: &str) -> Option<&'static str> {
+        let valid = !id.is_empty() && Path::new(id).components()
+            .all(|x| matches!(x, Component::Normal(_)));
+        if !valid || id.contains('\\') {
+            return Some("which is not a name in the outdir");
+        }
+        match self.notebook.is_generated(&format!("{}.html", id)) {
+            true => Some("which is a generated page"),
+            false => None,
+        }
+    }
+
+    pub fn write_all(&self) -> io::Result<()> {
+        let outdir = self.notebook.outdir();
+        for (id, note) in self.redirects() {
+            let outfile = outdir.join(format!("{}.html", id));
+            fs::create_dir_all(outfile.with_file_name(""))?;
+
+            let url = format!("{}{}.html", "../".repeat(id.matches('/').count()),
+                              note.id());
+            fs::File::create(&outfile)?
+                .write_all(render(note.title(), &url).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// A page that redirects to `url`
+fn render(title: &str, url: &str) -> String {
+    let (title, url) = (escape(title), escape(url));
+    format!(concat!(
+        "<!DOCTYPE html>\n",
+        "<html>\n",
+        "    <head>\n",
+        "        <title>{title}</title>\n",
+        "        <meta http-equiv=\"refresh\" content=\"0; url={url}\">\n",
+        "        <link rel=\"canonical\" href=\"{url}\">\n",
+        "    </head>\n",
+        "    <body>\n",
+        "        <p>Moved to <a href=\"{url}\">{title}</a>.</p>\n",
+        "    </body>\n",
+        "</html>\n"),
+        title = title, url = url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn redirects() {
+        let notebook = notebook(&[
+            ("cs/machine-learning.md", "---\naliases: [ML, index, ../up]\n\
+                                        redirect_from: [old/ml, graph, tags/ml]\n---\n"),
+            ("stats.md", "---\naliases: [other]\n---\n"),
+            ("other.md", "# Other\n"),
+        ]);
+
+        let ids: Vec<String> = Redirects::new(&notebook).redirects().into_iter()
+            .map(|(id, note)| format!("{} {}", id, note.id()))
+            .collect();
+        assert_eq!(ids, vec!["cs/ML cs/machine-learning", "old/ml cs/machine-learning"]);
+    }
+}
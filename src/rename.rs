@@ -0,0 +1,330 @@
+use pulldown_cmark::{BrokenLink, Event, Tag};
+use pulldown_cmark::Parser as CmarkParser;
+use std::path::{Component, Path, PathBuf};
+use std::ops::Range;
+use std::io;
+use std::fs;
+
+use crate::{Note, Notebook, Resolved, link_matches, yaml_len};
+use crate::links::{find_links, local_path, normalize};
+
+/// A link changed by `Notebook::move_note`
+pub struct Edit {
+    pub path: PathBuf,
+    pub line: usize,
+    pub old: String,
+    pub new: String,
+}
+
+/// Summary of `Notebook::move_note`
+pub struct Move {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub edits: Vec<Edit>,
+    /// Notes in other formats than markdown that link to the moved note.
+    /// Their links are not rewritten.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Move {
+    /// Number of notes with rewritten links
+    pub fn notes_changed(&self) -> usize {
+        let mut paths: Vec<&Path> = self.edits.iter()
+            .map(|x| x.path.as_path())
+            .collect();
+        paths.dedup();
+        paths.len()
+    }
+}
+
+/// Links to rewrite when moving note `from` to `to`
+pub(crate) struct Rewrites {
+    pub edits: Vec<Edit>,
+    /// New contents of the changed note files
+    pub files: Vec<(PathBuf, String)>,
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Find the links to rewrite when note `from` is moved to `to_path`, which
+/// gives it the id `to`.
+///
+/// Wikilinks are rewritten when they no longer resolve to the same note,
+/// which also covers links that become ambiguous because of the new id.
+/// Links are resolved like `Notebook::resolve`, so links through an alias
+/// stay as long as the alias still leads to the note. They
+/// keep their number of path components if that is unique, and their fragment
+/// and alias.
+///
+/// Relative markdown links to the note, and those in the note itself, get the
+/// new relative path.
+pub(crate) fn rewrite_links(notebook: &Notebook, from: &str, to: &str,
+                            to_path: &Path) -> io::Result<Rewrites> {
+    let mut ids = notebook.note_ids();
+    ids.sort_unstable();
+    let new_ids: Vec<&str> = ids.iter()
+        .map(|&id| if id == from { to } else { id })
+        .collect();
+    let notes: Vec<(&str, &Note)> = ids.iter()
+        .map(|&id| (id, notebook.get(id).unwrap()))
+        .collect();
+    let new_notes: Vec<(&str, &Note)> = new_ids.iter().copied()
+        .zip(notes.iter().map(|(_, note)| *note))
+        .collect();
+    let from_path = &notebook.get(from).unwrap().path;
+
+    let mut output = Rewrites { edits: vec![], files: vec![], skipped: vec![] };
+    for &id in &ids {
+        let note = notebook.get(id).unwrap();
+
+        if note.format.name() != "markdown" {
+            let links_here = notebook.links(note).iter().any(|link| {
+                matches!(notebook.resolve(&link.target),
+                         Resolved::Found(target) if target.id() == from)
+            });
+            if links_here {
+                output.skipped.push(note.path.clone());
+            }
+            continue;
+        }
+
+        let contents = fs::read_to_string(&note.path)?;
+        let new_path = if id == from { to_path } else { &note.path };
+        let mut replacements = vec![];
+
+        // Only look for links after the front matter, where pulldown-cmark
+        // would take lists like `tags: [x]` for links
+        let header = yaml_len(&contents);
+        let body = &contents[header..];
+
+        for link in find_links(body, notebook.compiler().parse_options) {
+            let old = match notebook.link_targets(&notes, &link.target)[..] {
+                [old] if !link.target.is_empty() => old,
+                _ => continue,
+            };
+            let new = if old == from { to } else { old };
+            if notebook.link_targets(&new_notes, &link.target) == [new] {
+                continue;
+            }
+
+            // Only replace the target, `span` covers the `[...]` around it
+            let span = header + link.span.start..header + link.span.end;
+            let text = &contents[span.clone()];
+            let inner = &text[1..text.len() - 1];
+            let end = inner.find(['#', '|']).unwrap_or(inner.len());
+            replacements.push((span, format!("[{}{}]",
+                reference(&new_ids, new, link.target.split('/').count()),
+                &inner[end..])));
+        }
+
+        let func = &mut |_: BrokenLink| None;
+        let parser = CmarkParser::new_with_broken_link_callback(
+            body, notebook.compiler().parse_options, Some(func));
+        for (event, range) in parser.into_offset_iter() {
+            let range = header + range.start..header + range.end;
+            let dest = match event {
+                Event::Start(Tag::Image(_, dest, _))
+                    | Event::Start(Tag::Link(_, dest, _)) => dest,
+                _ => continue,
+            };
+            let new_dest = match rewrite_dest(notebook, &dest, &note.path,
+                                              new_path, from_path, to_path) {
+                Some(new_dest) if new_dest != *dest => new_dest,
+                _ => continue,
+            };
+
+            // The destination comes after the `](` of inline links, other
+            // links are left alone
+            let text = &contents[range.clone()];
+            let start = match text.rfind("](") {
+                Some(i) if text[i..].contains(&*dest) =>
+                    range.start + i + text[i..].find(&*dest).unwrap(),
+                _ => continue,
+            };
+            replacements.push((start..start + dest.len(), new_dest));
+        }
+
+        if replacements.is_empty() {
+            continue;
+        }
+
+        replacements.sort_by_key(|(range, _)| range.start);
+        let mut new_contents = contents.clone();
+        for (range, text) in replacements.iter().rev() {
+            new_contents.replace_range(range.clone(), text);
+        }
+        for (range, text) in replacements {
+            output.edits.push(Edit {
+                path: note.path.clone(),
+                line: line(&contents, &range),
+                old: String::from(&contents[range]),
+                new: text,
+            });
+        }
+        output.files.push((note.path.clone(), new_contents));
+    }
+
+    Ok(output)
+}
+
+/// New destination of a markdown link in the note at `path`, which moves to
+/// `new_path`. `None` if the link does not change.
+fn rewrite_dest(notebook: &Notebook, dest: &str, path: &Path, new_path: &Path,
+                from_path: &Path, to_path: &Path) -> Option<String> {
+    let file = local_path(dest)?;
+    let suffix = &dest[dest.find(['#', '?']).unwrap_or(dest.len())..];
+    let basedir = notebook.basedir();
+
+    let absolute = file.starts_with('/');
+    let target = match absolute {
+        true => normalize(&basedir.join(&file[1..])),
+        false => normalize(&path.parent()?.join(&file)),
+    };
+
+    // Links to the note file, or to its compiled page
+    let target = if target.with_extension("") == normalize(from_path).with_extension("") {
+        match target.extension().and_then(|x| x.to_str()) {
+            Some("html") => to_path.with_extension("html"),
+            _ if target == normalize(from_path) => PathBuf::from(to_path),
+            _ => return None,
+        }
+    } else if path != new_path && !absolute {
+        target
+    } else {
+        return None;
+    };
+
+    let mut output = match absolute {
+        true => format!("/{}", relative(&normalize(basedir), &normalize(&target))),
+        false => relative(&normalize(new_path.parent()?), &normalize(&target)),
+    };
+    if dest.contains("%20") {
+        output = output.replace(' ', "%20");
+    }
+    output.push_str(suffix);
+    Some(output)
+}
+
+/// Reference to `id` for a link that used `parts` path components. Keeps
+/// that many components if they only match `id`, otherwise uses the shortest
+/// suffix of `id` on a directory boundary that does.
+fn reference<'a>(ids: &[&str], id: &'a str, parts: usize) -> &'a str {
+    let unique = |suffix: &&str| link_matches(ids.iter().copied(), suffix) == [id];
+    let suffixes: Vec<&str> = id.match_indices('/').map(|(i, _)| &id[i + 1..])
+        .rev()
+        .chain(std::iter::once(id))
+        .collect();
+
+    Some(suffixes[parts.min(suffixes.len()) - 1]).filter(unique)
+        .or_else(|| suffixes.into_iter().find(unique))
+        .unwrap_or(id)
+}
+
+/// Line number of the start of `range`, starting at 1
+fn line(contents: &str, range: &Range<usize>) -> usize {
+    contents[..range.start].matches('\n').count() + 1
+}
+
+/// Path from directory `from` to `to` as a `/`-separated link
+fn relative(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts = vec![String::from(".."); from.len() - common];
+    parts.extend(to[common..].iter()
+        .map(|c| c.as_os_str().to_string_lossy().to_string()));
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    fn moved(files: &[(&str, &str)], from: &str, to: &str, loose: bool)
+        -> Vec<(String, String)>
+    {
+        let mut notebook = notebook(files);
+        notebook.set_loose_links(loose);
+        notebook.move_note(from, Path::new(to), false).unwrap();
+
+        let mut output: Vec<(String, String)> = notebook.note_ids().iter()
+            .map(|id| {
+                let note = notebook.get(id).unwrap();
+                (id.to_string(), fs::read_to_string(&note.path).unwrap())
+            })
+            .collect();
+        output.sort();
+        output
+    }
+
+    fn files(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files.iter().map(|(id, x)| (id.to_string(), x.to_string())).collect()
+    }
+
+    #[test]
+    fn front_matter() {
+        let contents = "---\ntitle: A\naliases: [x, Old Name]\ntags: [x]\n---\n\
+                        [[x]] and [x]";
+        assert_eq!(moved(&[("a.md", contents), ("x.md", "")], "x", "sub/y", false),
+                   files(&[
+                       ("a", "---\ntitle: A\naliases: [x, Old Name]\ntags: [x]\n---\n\
+                              [[y]] and [y]"),
+                       ("sub/y", ""),
+                   ]));
+    }
+
+    #[test]
+    fn wikilinks() {
+        let links = "[[x#Part|shown]] [[x#^block]] [[dir/x|x]] [[ x ]] [[#Own]]";
+        assert_eq!(moved(&[("a.md", links), ("dir/x.md", "[[a]] [[#Own]]")],
+                         "dir/x", "other/y", false),
+                   files(&[
+                       ("a", "[[y#Part|shown]] [[y#^block]] [[other/y|x]] [[y]] [[#Own]]"),
+                       ("other/y", "[[a]] [[#Own]]"),
+                   ]));
+    }
+
+    #[test]
+    fn aliases() {
+        let files_before = [
+            ("a.md", "[[Old Name]] [[my note]] [[b]]"),
+            ("My_Note.md", "---\naliases: [Old Name]\n---\n"),
+            ("b.md", ""),
+        ];
+        // The alias still leads to the note, the loose name does not
+        assert_eq!(moved(&files_before, "My_Note", "archive/gone", true),
+                   files(&[
+                       ("a", "[[Old Name]] [[gone]] [[b]]"),
+                       ("archive/gone", "---\naliases: [Old Name]\n---\n"),
+                       ("b", ""),
+                   ]));
+    }
+
+    #[test]
+    fn markdown_links() {
+        let files_before = [
+            ("a.md", "[md](x.md), [page](x.html#top), ![img](pic.png) and \
+                      [abs](/x.md)"),
+            ("x.md", "[back](a.md) [web](https://x.md) [here](#top)"),
+        ];
+        assert_eq!(moved(&files_before, "x", "sub/y", false), files(&[
+            ("a", "[md](sub/y.md), [page](sub/y.html#top), ![img](pic.png) and \
+                   [abs](/sub/y.md)"),
+            ("sub/y", "[back](../a.md) [web](https://x.md) [here](#top)"),
+        ]));
+    }
+
+    #[test]
+    fn references() {
+        let ids = ["work/todo", "home/todo", "a/b/c", "x/b/c2"];
+
+        // `todo` is ambiguous, so the link needs the directory
+        assert_eq!(reference(&ids, "work/todo", 1), "work/todo");
+        assert_eq!(reference(&ids, "a/b/c", 1), "c");
+        // The number of components of the old link is kept if unique
+        assert_eq!(reference(&ids, "a/b/c", 2), "b/c");
+        assert_eq!(reference(&ids, "a/b/c", 5), "a/b/c");
+        assert_eq!(relative(Path::new("/n/a"), Path::new("/n/b/c.md")), "../b/c.md");
+    }
+}
@@ -0,0 +1,358 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use std::path::Path;
+use std::io;
+use std::fs;
+
+use crate::Note;
+use crate::check::Diagnostic;
+
+/// Fields that every note may have without declaring them
+const BUILTIN_FIELDS: &[&str] = &["title", "tags", "aliases", "type"];
+
+/// Declared front matter of notes, per folder or per `type`
+///
+/// The schema file has a section per group of notes, with a line per field
+/// and its type. Fields ending in `?` are optional:
+///
+/// ```text
+/// # Notes in papers/ and below
+/// folder papers:
+///   year: date
+///   authors: list
+///   venue: string?
+///   status: enum(draft, submitted, published)?
+///
+/// # Notes with `type: meeting`
+/// type meeting:
+///   date: date
+///
+/// # Every note
+/// all:
+///   draft: enum(true, false)?
+/// ```
+///
+/// A note is checked against all sections that apply to it. Fields that none
+/// of them declare are reported, except for `title`, `tags`, `aliases`,
+/// `type` and the flags of the publish profile. Notes that no section
+/// applies to are not checked.
+pub struct Schema {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    applies: Applies,
+    fields: Vec<Field>,
+}
+
+enum Applies {
+    All,
+    Folder(String),
+    Type(String),
+}
+
+struct Field {
+    name: String,
+    kind: Kind,
+    required: bool,
+}
+
+enum Kind {
+    String,
+    /// `YYYY-MM-DD`, optionally with a time
+    Date,
+    /// `[a, b]` or a block list
+    List,
+    Enum(Vec<String>),
+}
+
+impl Kind {
+    fn parse(input: &str) -> Option<Self> {
+        match input {
+            "string" => Some(Kind::String),
+            "date" => Some(Kind::Date),
+            "list" => Some(Kind::List),
+            _ => {
+                let values = input.strip_prefix("enum(")?.strip_suffix(')')?;
+                Some(Kind::Enum(crate::split_yaml_list(values)))
+            },
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Kind::String => String::from("string"),
+            Kind::Date => String::from("date"),
+            Kind::List => String::from("list"),
+            Kind::Enum(values) => format!("one of {}", values.join(", ")),
+        }
+    }
+
+    /// Problem with `value` for this type
+    fn check(&self, value: &str) -> Option<String> {
+        let value = value.trim();
+        let is_list = value.starts_with('[') && value.ends_with(']');
+        match self {
+            // A key without a value reads as an empty block list
+            Kind::String if value.is_empty() || value == "[]" =>
+                Some(String::from("is empty")),
+            Kind::String if is_list => Some(String::from("is a list, expected a string")),
+            Kind::Date => {
+                let value = value.trim_matches(|c| c == '"' || c == '\'');
+                let valid = NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+                    || ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"].iter()
+                        .any(|format| NaiveDateTime::parse_from_str(value, format).is_ok());
+                match valid {
+                    true => None,
+                    false => Some(format!("{:?} is not a date, expected YYYY-MM-DD", value)),
+                }
+            },
+            Kind::List if !is_list =>
+                Some(format!("{:?} is not a list, expected [a, b]", value)),
+            Kind::Enum(values) => {
+                let value = value.trim_matches(|c| c == '"' || c == '\'');
+                match values.iter().any(|x| x == value) {
+                    true => None,
+                    false => Some(format!("{:?} is not one of {}", value, values.join(", "))),
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Schema {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?).map_err(|(line, err)| io::Error::new(
+            io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, err)))
+    }
+
+    /// Parse a schema. Errors have the line number, starting at 1.
+    pub fn parse(input: &str) -> Result<Self, (usize, String)> {
+        let mut rules: Vec<Rule> = vec![];
+        for (i, line) in input.lines().enumerate() {
+            let err = |message: String| Err((i + 1, message));
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return err(format!("expected `name: type`, found {:?}", line.trim())),
+            };
+
+            // Sections are not indented, fields are
+            if !line.starts_with(char::is_whitespace) {
+                if !value.is_empty() {
+                    return err(format!("expected `{}:` to start a section", key));
+                }
+                let applies = match key.split_once(char::is_whitespace) {
+                    None if key == "all" => Applies::All,
+                    Some(("folder", dir)) => Applies::Folder(
+                        String::from(dir.trim().trim_matches(|c| c == '"' || c == '/'))),
+                    Some(("type", name)) => Applies::Type(
+                        String::from(name.trim().trim_matches('"'))),
+                    _ => return err(format!(
+                        "unknown section {:?}, expected `folder DIR`, `type NAME` or `all`", key)),
+                };
+                rules.push(Rule { applies, fields: vec![] });
+                continue;
+            }
+
+            let rule = match rules.last_mut() {
+                Some(rule) => rule,
+                None => return err(String::from("field outside of a section")),
+            };
+            let (kind, required) = match value.strip_suffix('?') {
+                Some(kind) => (kind.trim(), false),
+                None => (value, true),
+            };
+            let kind = match Kind::parse(kind) {
+                Some(kind) => kind,
+                None => return err(format!(
+                    "unknown type {:?}, expected string, date, list or enum(a, b)", kind)),
+            };
+            rule.fields.push(Field { name: String::from(key), kind, required });
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Check the front matter of `note`. `flags` are allowed in every note,
+    /// like the flags of the publish profile.
+    pub fn validate(&self, note: &Note, flags: &[String]) -> Vec<Diagnostic> {
+        let contents = fs::read_to_string(&note.path)
+            .map(|x| note.format.to_markdown(&x))
+            .unwrap_or_default();
+        let lines: Vec<String> = match contents.lines().next() {
+            Some("---") => contents.lines().skip(1)
+                .take_while(|&line| line != "---")
+                .map(String::from)
+                .collect(),
+            _ => vec![],
+        };
+        let values = crate::split_yaml_pairs(&lines);
+
+        let rules: Vec<&Rule> = self.rules.iter()
+            .filter(|rule| match &rule.applies {
+                Applies::All => true,
+                Applies::Folder(dir) => dir.is_empty()
+                    || note.id().starts_with(&format!("{}/", dir)),
+                Applies::Type(name) => values.get("type") == Some(name),
+            })
+            .collect();
+        if rules.is_empty() {
+            return vec![];
+        }
+        let fields: Vec<&Field> = rules.iter().flat_map(|rule| &rule.fields).collect();
+
+        // Line of a top level key, the front matter starts on line 2
+        let position = |key: &str| lines.iter()
+            .position(|line| line.split_once(':').is_some_and(|(k, _)| k.trim() == key)
+                      && !line.starts_with(char::is_whitespace))
+            .map(|i| (i + 2, 1));
+        let diagnostic = |position, message| Diagnostic {
+            path: note.path.clone(),
+            position,
+            message,
+        };
+
+        let mut output = vec![];
+        for (i, field) in fields.iter().enumerate() {
+            // A field declared by several sections is checked once, against
+            // the first, and required if any section requires it
+            if fields[..i].iter().any(|x| x.name == field.name) {
+                continue;
+            }
+            let required = fields[i..].iter()
+                .any(|x| x.name == field.name && x.required);
+
+            match values.get(&field.name) {
+                Some(value) => {
+                    if let Some(problem) = field.kind.check(value) {
+                        output.push(diagnostic(position(&field.name),
+                            format!("field {}: {}", field.name, problem)));
+                    }
+                },
+                None if required => output.push(diagnostic(None, format!(
+                    "missing required field {} ({})", field.name, field.kind.name()))),
+                None => (),
+            }
+        }
+
+        let known: Vec<&str> = fields.iter().map(|x| x.name.as_str())
+            .chain(BUILTIN_FIELDS.iter().copied())
+            .chain(flags.iter().map(|x| x.as_str()))
+            .collect();
+        let mut unknown: Vec<&String> = values.keys()
+            .filter(|key| !known.contains(&key.as_str()))
+            .collect();
+        unknown.sort();
+        for key in unknown {
+            let message = match known.iter().find(|x| distance(key, x) <= 2) {
+                Some(similar) => format!("unknown field {}, did you mean {}?", key, similar),
+                None => format!("unknown field {}", key),
+            };
+            output.push(diagnostic(position(key), message));
+        }
+
+        output.sort_by_key(|x| x.position);
+        output
+    }
+}
+
+/// Edit distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = (previous + (ca != *cb) as usize)
+                .min(row[j] + 1)
+                .min(current + 1);
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    const SCHEMA: &str = "\
+# Papers
+folder papers:
+  year: date
+  authors: list
+  venue: string?
+  status: enum(draft, submitted)?
+
+type meeting:
+  date: date
+all:
+  draft: enum(true, false)?
+";
+
+    fn problems(schema: &Schema, contents: &str) -> Vec<String> {
+        let notebook = notebook(&[("papers/note.md", contents)]);
+        schema.validate(notebook.get("papers/note").unwrap(), &[String::from("private")])
+            .into_iter()
+            .map(|x| format!("{:?} {}", x.position.map(|(line, _)| line), x.message))
+            .collect()
+    }
+
+    #[test]
+    fn parse() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.rules.len(), 3);
+        assert!(matches!(&schema.rules[0].applies, Applies::Folder(dir) if dir == "papers"));
+        assert!(matches!(&schema.rules[1].applies, Applies::Type(name) if name == "meeting"));
+        assert!(matches!(schema.rules[2].applies, Applies::All));
+        let status = &schema.rules[0].fields[3];
+        assert_eq!((status.name.as_str(), status.required), ("status", false));
+        assert_eq!(status.kind.name(), "one of draft, submitted");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |input| Schema::parse(input).err().unwrap();
+        assert_eq!(error("  year: date\n").0, 1);
+        assert_eq!(error("all:\n  year: number\n"),
+                   (2, String::from("unknown type \"number\", expected string, date, \
+                                     list or enum(a, b)")));
+        assert_eq!(error("all:\n\ntag x:\n").0, 3);
+        assert_eq!(error("all: x\n").0, 1);
+        assert_eq!(error("all:\n  no type\n").0, 2);
+    }
+
+    #[test]
+    fn validate() {
+        let schema = Schema::parse(SCHEMA).unwrap();
+
+        assert!(problems(&schema, "---\nyear: 2024-01-01\nauthors: [a]\n\
+                                   tags: [x]\nprivate: true\n---\n").is_empty());
+        assert_eq!(problems(&schema, "---\nyear: 2024\nauthors:\n  - a\nvenue:\n\
+                                      status: done\ndraft: true\nyaer: 2024\n---\n"), vec![
+            "Some(2) field year: \"2024\" is not a date, expected YYYY-MM-DD",
+            "Some(5) field venue: is empty",
+            "Some(6) field status: \"done\" is not one of draft, submitted",
+            "Some(8) unknown field yaer, did you mean year?",
+        ]);
+        assert_eq!(problems(&schema, "# No front matter\n"), vec![
+            "None missing required field year (date)",
+            "None missing required field authors (list)",
+        ]);
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("year", "year"), 0);
+        assert_eq!(distance("yaer", "year"), 2);
+        assert_eq!(distance("autors", "authors"), 1);
+        assert_eq!(distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,414 @@
+mod query;
+pub use query::{Query, QueryError};
+pub mod site;
+
+use pulldown_cmark::{Event, Options};
+use pulldown_cmark::Parser as CmarkParser;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use std::fs;
+
+use crate::{Note, Notebook};
+
+/// First line of the index file, bumped when the format changes
+const INDEX_HEADER: &str = "notes-search-index 1";
+
+/// Searchable parts of a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    Title,
+    Body,
+    Tag,
+    /// All front matter values except the title and tags
+    Meta,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Title, Field::Body, Field::Tag, Field::Meta];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Body => "body",
+            Field::Tag => "tag",
+            Field::Meta => "meta",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    /// Score multiplier for matches in this field
+    fn weight(&self) -> f32 {
+        match self {
+            Field::Title => 3.0,
+            Field::Tag => 2.0,
+            Field::Meta => 1.5,
+            Field::Body => 1.0,
+        }
+    }
+}
+
+/// Occurrences of a term in one field of a note
+#[derive(Debug, Clone)]
+struct Posting {
+    doc: String,
+    field: Field,
+    positions: Vec<u32>,
+}
+
+/// A search result
+#[derive(Debug)]
+pub struct Hit {
+    /// Note id
+    pub id: String,
+    pub score: f32,
+}
+
+/// Inverted index over all notes in a notebook, stored on disk so only
+/// changed notes have to be indexed again.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// {Note id, modification time in seconds}
+    docs: HashMap<String, u64>,
+    /// {Term, postings}. Tags are stored as a single term each.
+    terms: BTreeMap<String, Vec<Posting>>,
+}
+
+impl SearchIndex {
+    /// Load an index from `file`. A missing or outdated index file results in
+    /// an empty index.
+    pub fn load(file: &Path) -> io::Result<Self> {
+        let mut index = Self::default();
+        let file = match fs::File::open(file) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(index),
+            Err(e) => return Err(e),
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        if lines.next().transpose()?.as_deref() != Some(INDEX_HEADER) {
+            return Ok(index);
+        }
+
+        for line in lines {
+            let line = line?;
+            let parts: Vec<&str> = line.split('\t').collect();
+            match parts[..] {
+                ["doc", id, mtime] => {
+                    index.docs.insert(String::from(id),
+                                      mtime.parse().unwrap_or(0));
+                },
+                ["term", term, field, doc, positions] => {
+                    let field = match Field::from_name(field) {
+                        Some(field) => field,
+                        None => continue,
+                    };
+                    index.terms.entry(String::from(term)).or_default()
+                        .push(Posting {
+                            doc: String::from(doc),
+                            field,
+                            positions: positions.split(',')
+                                .filter_map(|x| x.parse().ok())
+                                .collect(),
+                        });
+                },
+                _ => continue,
+            }
+        }
+
+        Ok(index)
+    }
+
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(fs::File::create(file)?);
+
+        writeln!(out, "{}", INDEX_HEADER)?;
+        for (id, mtime) in &self.docs {
+            writeln!(out, "doc\t{}\t{}", id, mtime)?;
+        }
+        for (term, postings) in &self.terms {
+            for p in postings {
+                let positions: Vec<String> = p.positions.iter()
+                    .map(|x| x.to_string()).collect();
+                writeln!(out, "term\t{}\t{}\t{}\t{}", term, p.field.name(),
+                         p.doc, positions.join(","))?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Bring the index up to date with the notebook. Only notes that changed
+    /// since they were indexed are read. Returns the number of notes
+    /// (re)indexed and removed.
+    pub fn update(&mut self, notebook: &Notebook) -> (usize, usize) {
+        let ids: HashSet<&str> = notebook.note_ids().into_iter().collect();
+
+        let removed: Vec<String> = self.docs.keys()
+            .filter(|id| !ids.contains(id.as_str()))
+            .cloned().collect();
+        for id in &removed {
+            self.remove(id);
+        }
+
+        let mut updated = 0;
+        for id in ids {
+            let note = notebook.get(id).unwrap();
+            let mtime = note.modified()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            if self.docs.get(id) == Some(&mtime) {
+                continue;
+            }
+
+            self.remove(id);
+            self.add(id, note, mtime);
+            updated += 1;
+        }
+
+        (updated, removed.len())
+    }
+
+    fn remove(&mut self, id: &str) {
+        if self.docs.remove(id).is_none() {
+            return;
+        }
+        for postings in self.terms.values_mut() {
+            postings.retain(|p| p.doc != id);
+        }
+        self.terms.retain(|_, postings| !postings.is_empty());
+    }
+
+    fn add(&mut self, id: &str, note: &Note, mtime: u64) {
+        let body = plain_text(&note.read());
+        let meta: Vec<String> = note.metadata.borrow().iter()
+            .filter(|(k, _)| k.as_str() != "title" && k.as_str() != "tags")
+            .map(|(_, v)| v.clone())
+            .collect();
+
+        let mut fields = vec![
+            (Field::Title, tokenize(note.title())),
+            (Field::Body, tokenize(&body)),
+            (Field::Meta, tokenize(&meta.join(" "))),
+        ];
+        fields.push((Field::Tag, note.tags().iter()
+            .map(|tag| (tag.to_lowercase(), 0..0))
+            .collect()));
+
+        for (field, tokens) in fields {
+            let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+            for (i, (token, _)) in tokens.into_iter().enumerate() {
+                positions.entry(token).or_default().push(i as u32);
+            }
+            for (term, positions) in positions {
+                self.terms.entry(term).or_default().push(Posting {
+                    doc: String::from(id),
+                    field,
+                    positions,
+                });
+            }
+        }
+
+        self.docs.insert(String::from(id), mtime);
+    }
+
+    /// Find notes matching `query`, best match first
+    pub fn search(&self, query: &Query) -> Vec<Hit> {
+        let mut hits: Vec<Hit> = self.eval(query).into_iter()
+            .map(|(id, score)| Hit { id, score })
+            .collect();
+        hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap()
+                     .then_with(|| a.id.cmp(&b.id)));
+        hits
+    }
+
+    /// {Note id, score} of all notes matching `query`
+    fn eval(&self, query: &Query) -> HashMap<String, f32> {
+        match query {
+            Query::And(a, b) => {
+                let b = self.eval(b);
+                self.eval(a).into_iter()
+                    .filter_map(|(id, x)| b.get(&id).map(|y| (id, x + y)))
+                    .collect()
+            },
+            Query::Or(a, b) => {
+                let mut a = self.eval(a);
+                for (id, y) in self.eval(b) {
+                    *a.entry(id).or_insert(0.0) += y;
+                }
+                a
+            },
+            Query::Not(a) => {
+                let a = self.eval(a);
+                self.docs.keys()
+                    .filter(|id| !a.contains_key(*id))
+                    .map(|id| (id.clone(), 0.0))
+                    .collect()
+            },
+            Query::Path(path) => {
+                let path = path.to_lowercase();
+                self.docs.keys()
+                    .filter(|id| id.to_lowercase().contains(&path))
+                    .map(|id| (id.clone(), 0.0))
+                    .collect()
+            },
+            Query::Tag(tag) => {
+                let tag = tag.to_lowercase();
+                let subtags = format!("{}/", tag);
+                let mut output = HashMap::new();
+                for (_, postings) in self.terms.range(tag.clone()..)
+                    .take_while(|(term, _)| term.starts_with(&tag))
+                    .filter(|(term, _)| **term == tag || term.starts_with(&subtags))
+                {
+                    for p in postings.iter().filter(|p| p.field == Field::Tag) {
+                        *output.entry(p.doc.clone()).or_insert(0.0) +=
+                            Field::Tag.weight();
+                    }
+                }
+                output
+            },
+            Query::Terms { field, terms, prefix } => {
+                self.eval_terms(*field, terms, *prefix)
+            },
+        }
+    }
+
+    /// Match a term or phrase. With `prefix`, the last term matches any term
+    /// starting with it.
+    fn eval_terms(&self, field: Option<Field>, terms: &[String], prefix: bool)
+        -> HashMap<String, f32>
+    {
+        let n = self.docs.len().max(1) as f32;
+        let field_ok = |p: &Posting| match field {
+            Some(f) => p.field == f,
+            None => p.field != Field::Tag,
+        };
+
+        // Postings per term of the phrase
+        let postings: Vec<Vec<&Posting>> = terms.iter().enumerate()
+            .map(|(i, term)| {
+                if prefix && i + 1 == terms.len() {
+                    self.terms.range(term.clone()..)
+                        .take_while(|(t, _)| t.starts_with(term.as_str()))
+                        .flat_map(|(_, p)| p.iter())
+                        .filter(|p| field_ok(p))
+                        .collect()
+                } else {
+                    self.terms.get(term).map_or_else(Vec::new,
+                        |p| p.iter().filter(|p| field_ok(p)).collect())
+                }
+            })
+            .collect();
+
+        let mut output = HashMap::new();
+        let first = match postings.first() {
+            Some(first) => first,
+            None => return output,
+        };
+        let df = first.iter().map(|p| &p.doc).collect::<HashSet<_>>().len();
+        let idf = (1.0 + n / df.max(1) as f32).ln();
+
+        for start in first {
+            // Positions where the whole phrase matches
+            let mut positions: Vec<u32> = start.positions.clone();
+            for (i, next) in postings.iter().enumerate().skip(1) {
+                let next: HashSet<u32> = next.iter()
+                    .filter(|p| p.doc == start.doc && p.field == start.field)
+                    .flat_map(|p| p.positions.iter().copied())
+                    .collect();
+                positions.retain(|pos| next.contains(&(pos + i as u32)));
+            }
+
+            if !positions.is_empty() {
+                *output.entry(start.doc.clone()).or_insert(0.0) +=
+                    positions.len() as f32 * idf * start.field.weight();
+            }
+        }
+        output
+    }
+}
+
+/// The text of a markdown document, without markup
+pub fn plain_text(markdown: &str) -> String {
+    let mut output = String::new();
+    for event in CmarkParser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Text(text) | Event::Code(text) => output.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => output.push(' '),
+            Event::End(_) => output.push('\n'),
+            _ => (),
+        }
+    }
+    output
+}
+
+/// Split text into lowercase words, with their location in `text`
+pub fn tokenize(text: &str) -> Vec<(String, Range<usize>)> {
+    let mut tokens = vec![];
+    let mut start = None;
+
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((text[s..i].to_lowercase(), s..i));
+                start = None;
+            },
+            _ => (),
+        }
+    }
+    tokens
+}
+
+/// Part of `text` around the first match of `query`, with the matching words
+/// wrapped in `before` and `after`.
+pub fn snippet(text: &str, query: &Query, width: usize, before: &str,
+               after: &str) -> String {
+    let words = query.positive_terms();
+    let matches = |token: &str| words.iter().any(|(word, prefix)| {
+        if *prefix { token.starts_with(word.as_str()) } else { token == word }
+    });
+
+    let tokens = tokenize(text);
+    let first = tokens.iter().find(|(t, _)| matches(t))
+        .map_or(0, |(_, range)| range.start);
+
+    // Start the snippet a bit before the first match, on a char boundary
+    let mut start = first.saturating_sub(width / 4);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (start + width).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut output = String::new();
+    if start > 0 {
+        output.push_str("...");
+    }
+    let mut last = start;
+    for (token, range) in &tokens {
+        if range.start < start || range.end > end || !matches(token) {
+            continue;
+        }
+        output.push_str(&text[last..range.start]);
+        output.push_str(before);
+        output.push_str(&text[range.clone()]);
+        output.push_str(after);
+        last = range.end;
+    }
+    output.push_str(&text[last..end]);
+    if end < text.len() {
+        output.push_str("...");
+    }
+
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
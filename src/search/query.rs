@@ -0,0 +1,298 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::search::{tokenize, Field};
+
+/// A parsed search query
+///
+/// Words are matched in the title, body and metadata of notes. The syntax
+/// supports:
+///
+/// - `"exact phrase"`
+/// - `prefix*`
+/// - `a AND b` (also just `a b`), `a OR b`, `NOT a` (also `-a`) and `( )`
+/// - field filters: `tag:course`, `title:word`, `path:work/`, `body:`, `meta:`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A word or phrase, optionally limited to one field
+    Terms {
+        field: Option<Field>,
+        terms: Vec<String>,
+        /// The last term is a prefix
+        prefix: bool,
+    },
+    /// Notes with the tag or one of its subtags
+    Tag(String),
+    /// Notes whose id contains the text
+    Path(String),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug)]
+pub struct QueryError(String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid query: {}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word {
+        field: Option<String>,
+        text: String,
+        quoted: bool,
+    },
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let mut tokens = lex(input).into_iter().peekable();
+
+        let query = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(query),
+            Some(token) => Err(QueryError(format!("unexpected {:?}", token))),
+        }
+    }
+
+    /// Words that should be highlighted in results, with whether they are a
+    /// prefix
+    pub fn positive_terms(&self) -> Vec<(String, bool)> {
+        match self {
+            Query::Terms { terms, prefix, .. } => terms.iter().enumerate()
+                .map(|(i, t)| (t.clone(), *prefix && i + 1 == terms.len()))
+                .collect(),
+            Query::And(a, b) | Query::Or(a, b) => {
+                let mut terms = a.positive_terms();
+                terms.extend(b.positive_terms());
+                terms
+            },
+            Query::Tag(_) | Query::Path(_) | Query::Not(_) => vec![],
+        }
+    }
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Token>>;
+
+fn parse_or(tokens: &mut Tokens) -> Result<Query, QueryError> {
+    let mut query = parse_and(tokens)?;
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        query = Query::Or(Box::new(query), Box::new(parse_and(tokens)?));
+    }
+    Ok(query)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Query, QueryError> {
+    let mut query = parse_unary(tokens)?;
+    loop {
+        match tokens.peek() {
+            Some(Token::And) => {
+                tokens.next();
+            },
+            Some(Token::Or) | Some(Token::Close) | None => break,
+            _ => (),
+        }
+        query = Query::And(Box::new(query), Box::new(parse_unary(tokens)?));
+    }
+    Ok(query)
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Result<Query, QueryError> {
+    match tokens.next() {
+        Some(Token::Not) => Ok(Query::Not(Box::new(parse_unary(tokens)?))),
+        Some(Token::Open) => {
+            let query = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(query),
+                _ => Err(QueryError(String::from("missing )"))),
+            }
+        },
+        Some(Token::Word { field, text, quoted }) => word(field, text, quoted),
+        Some(token) => Err(QueryError(format!("unexpected {:?}", token))),
+        None => Err(QueryError(String::from("unexpected end of query"))),
+    }
+}
+
+/// Turn a (field prefixed) word into a query
+fn word(field: Option<String>, text: String, quoted: bool)
+    -> Result<Query, QueryError>
+{
+    let field = match field.as_deref() {
+        Some("tag") => {
+            return Ok(Query::Tag(text.trim_start_matches('#').to_string()));
+        },
+        Some("path") => return Ok(Query::Path(text)),
+        Some(name) => Some(Field::from_name(name).ok_or_else(||
+            QueryError(format!("unknown field {:?}", name)))?),
+        None => None,
+    };
+
+    let prefix = !quoted && text.ends_with('*');
+    let terms: Vec<String> = tokenize(text.trim_end_matches('*')).into_iter()
+        .map(|(t, _)| t)
+        .collect();
+    if terms.is_empty() {
+        return Err(QueryError(format!("no words in {:?}", text)));
+    }
+
+    Ok(Query::Terms { field, terms, prefix })
+}
+
+fn lex(input: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            },
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            },
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            },
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            },
+            _ => {
+                let (text, quoted) = lex_word(&mut chars);
+
+                // Field filters, e.g. `title:word` or `title:"a phrase"`
+                let token = match text.split_once(':') {
+                    Some((field, "")) if !quoted && chars.peek() == Some(&'"') => {
+                        let field = Some(field.to_lowercase());
+                        let (text, quoted) = lex_word(&mut chars);
+                        Token::Word { field, text, quoted }
+                    },
+                    Some((field, value)) if !quoted && !value.is_empty() =>
+                        Token::Word {
+                            field: Some(field.to_lowercase()),
+                            text: String::from(value),
+                            quoted,
+                        },
+                    _ => match text.as_str() {
+                        "AND" if !quoted => Token::And,
+                        "OR" if !quoted => Token::Or,
+                        "NOT" if !quoted => Token::Not,
+                        _ => Token::Word { field: None, text, quoted },
+                    },
+                };
+                tokens.push(token);
+            },
+        }
+    }
+
+    tokens
+}
+
+/// Read a quoted phrase or a word ending at whitespace or a parenthesis. A
+/// field name ends at the quote of a phrase after it.
+fn lex_word(chars: &mut Peekable<Chars>) -> (String, bool) {
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        let text: String = chars.by_ref().take_while(|&c| c != '"').collect();
+        return (text, true);
+    }
+
+    let mut text = String::new();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() || c == '(' || c == ')'
+            || (c == '"' && text.ends_with(':'))
+        {
+            break;
+        }
+        text.push(c);
+        chars.next();
+    }
+    (text, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(field: Option<Field>, terms: &[&str], prefix: bool) -> Query {
+        Query::Terms {
+            field,
+            terms: terms.iter().map(|x| String::from(*x)).collect(),
+            prefix,
+        }
+    }
+
+    fn and(a: Query, b: Query) -> Query {
+        Query::And(Box::new(a), Box::new(b))
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(Query::parse("Quantum").unwrap(), terms(None, &["quantum"], false));
+        assert_eq!(Query::parse("quant*").unwrap(), terms(None, &["quant"], true));
+        assert_eq!(Query::parse("\"Exact phrase*\"").unwrap(),
+                   terms(None, &["exact", "phrase"], false));
+        assert_eq!(Query::parse("a b").unwrap(),
+                   and(terms(None, &["a"], false), terms(None, &["b"], false)));
+    }
+
+    #[test]
+    fn operators() {
+        let (a, b, c) = (terms(None, &["a"], false), terms(None, &["b"], false),
+                         terms(None, &["c"], false));
+
+        // AND binds stronger than OR
+        assert_eq!(Query::parse("a OR b AND c").unwrap(), Query::Or(
+            Box::new(a.clone()), Box::new(and(b.clone(), c.clone()))));
+        assert_eq!(Query::parse("(a OR b) c").unwrap(), and(
+            Query::Or(Box::new(a.clone()), Box::new(b.clone())), c.clone()));
+        assert_eq!(Query::parse("a -b").unwrap(),
+                   and(a.clone(), Query::Not(Box::new(b.clone()))));
+        assert_eq!(Query::parse("NOT a").unwrap(), Query::Not(Box::new(a)));
+        // Quoted operators are words
+        assert_eq!(Query::parse("\"OR\"").unwrap(), terms(None, &["or"], false));
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(Query::parse("tag:#course/physics").unwrap(),
+                   Query::Tag(String::from("course/physics")));
+        assert_eq!(Query::parse("path:work/").unwrap(),
+                   Query::Path(String::from("work/")));
+        assert_eq!(Query::parse("Title:\"two words\"").unwrap(),
+                   terms(Some(Field::Title), &["two", "words"], false));
+        assert_eq!(Query::parse("meta:draft*").unwrap(),
+                   terms(Some(Field::Meta), &["draft"], true));
+    }
+
+    #[test]
+    fn errors() {
+        for input in ["", "(a", "a)", "a OR", "color:red", "***"] {
+            assert!(Query::parse(input).is_err(), "{:?} should not parse", input);
+        }
+        assert_eq!(Query::parse("size:big").unwrap_err().to_string(),
+                   "Invalid query: unknown field \"size\"");
+    }
+
+    #[test]
+    fn positive_terms() {
+        let query = Query::parse("alpha OR bet* -gamma tag:x").unwrap();
+        assert_eq!(query.positive_terms(), vec![
+            (String::from("alpha"), false),
+            (String::from("bet"), true),
+        ]);
+    }
+}
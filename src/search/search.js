@@ -0,0 +1,152 @@
+// Search widget for notebooks compiled by `notes`. Loads `search-index.js`
+// from the same directory as this script, and adds a search box to the top of
+// the page.
+(function() {
+    "use strict";
+
+    var root = document.currentScript.src.replace(/[^\/]*$/, "");
+    var index = null;
+
+    // Keep in sync with `stem` in src/search/site.rs
+    function stem(word) {
+        var rules = [["ies", "y"], ["ied", "y"], ["ing", ""], ["ness", ""],
+                     ["ment", ""], ["ed", ""], ["ly", ""]];
+        var len = Array.from(word).length;
+        for (var i = 0; i < rules.length; i++) {
+            var suffix = rules[i][0];
+            if (word.endsWith(suffix) && len - suffix.length >= 3) {
+                return word.slice(0, -suffix.length) + rules[i][1];
+            }
+        }
+        if (!/(ss|us|is)$/.test(word) && word.endsWith("s") && len - 1 >= 3) {
+            return word.slice(0, -1);
+        }
+        return word;
+    }
+
+    function terms(text) {
+        return (text.toLowerCase().match(/[\p{L}\p{N}]+/gu) || [])
+            .filter(function(t) {
+                return Array.from(t).length > 1 && index.stop.indexOf(t) < 0;
+            })
+            .map(stem);
+    }
+
+    function idf(postings) {
+        return Math.log(1 + index.docs.length / (postings.length / 2));
+    }
+
+    // {doc: score} for a term, with prefix matching for the last query word
+    function lookup(term, prefix) {
+        var scores = {};
+        var add = function(postings) {
+            var weight = idf(postings);
+            for (var i = 0; i < postings.length; i += 2) {
+                var doc = postings[i];
+                scores[doc] = (scores[doc] || 0) + postings[i + 1] * weight;
+            }
+        };
+
+        if (index.terms[term]) {
+            add(index.terms[term]);
+        }
+        if (prefix) {
+            for (var t in index.terms) {
+                if (t !== term && t.startsWith(term)) {
+                    add(index.terms[t]);
+                }
+            }
+        }
+        return scores;
+    }
+
+    // Notes containing all query terms, best match first
+    function search(query) {
+        var words = terms(query);
+        if (words.length === 0) {
+            return [];
+        }
+
+        var scores = null;
+        words.forEach(function(word, i) {
+            var found = lookup(word, i === words.length - 1);
+            if (scores === null) {
+                scores = found;
+                return;
+            }
+            for (var doc in scores) {
+                if (found[doc] === undefined) {
+                    delete scores[doc];
+                } else {
+                    scores[doc] += found[doc];
+                }
+            }
+        });
+
+        return Object.keys(scores)
+            .sort(function(a, b) { return scores[b] - scores[a]; })
+            .slice(0, 10)
+            .map(function(doc) { return index.docs[doc]; });
+    }
+
+    function render(list, results) {
+        list.innerHTML = "";
+        results.forEach(function(doc) {
+            var item = document.createElement("li");
+            var link = document.createElement("a");
+            link.href = root + doc.u;
+            link.textContent = doc.t;
+            item.appendChild(link);
+
+            if (doc.g.length > 0) {
+                var tags = document.createElement("span");
+                tags.className = "tags";
+                tags.textContent = doc.g.map(function(t) { return "#" + t; })
+                    .join(" ");
+                item.appendChild(tags);
+            }
+            list.appendChild(item);
+        });
+    }
+
+    function load(callback) {
+        if (index !== null) {
+            return callback();
+        }
+        // A script element rather than fetch, which fails for file:// urls
+        var script = document.createElement("script");
+        script.src = root + "search-index.js";
+        script.onload = function() {
+            index = window.NOTES_SEARCH;
+            callback();
+        };
+        document.head.appendChild(script);
+    }
+
+    document.addEventListener("DOMContentLoaded", function() {
+        var widget = document.createElement("div");
+        widget.id = "notes-search";
+
+        var input = document.createElement("input");
+        input.type = "search";
+        input.placeholder = "Search";
+        var list = document.createElement("ul");
+
+        widget.appendChild(input);
+        widget.appendChild(list);
+        document.body.insertBefore(widget, document.body.firstChild);
+
+        input.addEventListener("input", function() {
+            load(function() { render(list, search(input.value)); });
+        });
+        input.addEventListener("keydown", function(e) {
+            var first = list.querySelector("a");
+            if (e.key === "Enter" && first) {
+                window.location.href = first.href;
+            } else if (e.key === "Escape") {
+                input.value = "";
+                render(list, []);
+            }
+        });
+    });
+})();
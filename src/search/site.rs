@@ -0,0 +1,194 @@
+use pulldown_cmark::{Event, Options, Tag};
+use pulldown_cmark::Parser as CmarkParser;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::fs;
+
+use crate::Notebook;
+use crate::json;
+use crate::search::{plain_text, tokenize};
+
+/// File name of the generated index, a script that sets `NOTES_SEARCH`, in
+/// the root of the outdir
+pub const SITE_INDEX: &str = "search-index.js";
+/// File name of the search widget, in the root of the outdir
+pub const SITE_SCRIPT: &str = "search.js";
+
+const SCRIPT: &str = include_str!("search.js");
+
+/// Words left out of the index. The widget drops them from queries as well.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in",
+    "is", "it", "of", "on", "or", "that", "the", "this", "to", "was", "with",
+];
+
+/// Term frequency multipliers for words in titles and headings
+const TITLE_WEIGHT: u32 = 5;
+const HEADING_WEIGHT: u32 = 2;
+
+/// Strip common English suffixes, so `studies`, `studied` and `study` are the
+/// same term. The widget applies the same rules to queries, so keep `stem` in
+/// `search.js` in sync with this.
+pub fn stem(word: &str) -> String {
+    let len = word.chars().count();
+    let strip = |suffix: &str, with: &str| -> Option<String> {
+        let rest = word.strip_suffix(suffix)?;
+        if len - suffix.chars().count() < 3 {
+            return None;
+        }
+        Some(format!("{}{}", rest, with))
+    };
+
+    strip("ies", "y")
+        .or_else(|| strip("ied", "y"))
+        .or_else(|| strip("ing", ""))
+        .or_else(|| strip("ness", ""))
+        .or_else(|| strip("ment", ""))
+        .or_else(|| strip("ed", ""))
+        .or_else(|| strip("ly", ""))
+        .or_else(|| match word.ends_with("ss") || word.ends_with("us")
+                           || word.ends_with("is") {
+            true => None,
+            false => strip("s", ""),
+        })
+        .unwrap_or_else(|| String::from(word))
+}
+
+/// Index words for the site search, without stop words
+fn terms(text: &str) -> Vec<String> {
+    tokenize(text).into_iter()
+        .map(|(token, _)| token)
+        .filter(|token| token.chars().count() > 1)
+        .filter(|token| !STOP_WORDS.contains(&token.as_str()))
+        .map(|token| stem(&token))
+        .collect()
+}
+
+/// Text of all headings in a markdown document
+fn headings(markdown: &str) -> Vec<String> {
+    let mut headings = vec![];
+    let mut current: Option<String> = None;
+    for event in CmarkParser::new_ext(markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(_)) => current = Some(String::new()),
+            Event::End(Tag::Heading(_)) => headings.extend(current.take()),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = &mut current {
+                    heading.push_str(&text);
+                }
+            },
+            _ => (),
+        }
+    }
+    headings
+}
+
+/// Writes the search index and widget for the compiled site
+pub struct SiteSearch<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> SiteSearch<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    pub fn write_all(&self) -> io::Result<()> {
+        let outdir = self.notebook.outdir();
+        fs::create_dir_all(&outdir)?;
+
+        let outfile = outdir.join(SITE_INDEX);
+        println!("Writing to {}", outfile.display());
+        // A script rather than JSON, so it also loads from file:// urls
+        let mut file = fs::File::create(&outfile)?;
+        writeln!(file, "var NOTES_SEARCH = {};", self.to_json().trim_end())?;
+        fs::File::create(outdir.join(SITE_SCRIPT))?
+            .write_all(SCRIPT.as_bytes())
+    }
+
+    /// The index as JSON
+    ///
+    /// `docs` contains the url (relative to the outdir), title, headings and
+    /// tags of every published note. `terms` maps each stemmed term to a flat
+    /// list of `doc index, frequency` pairs.
+    pub fn to_json(&self) -> String {
+        let mut ids = self.notebook.note_ids();
+        ids.sort_unstable();
+
+        let mut docs = vec![];
+        let mut index: BTreeMap<String, Vec<(usize, u32)>> = BTreeMap::new();
+        for id in ids {
+            let note = self.notebook.get(id).unwrap();
+            if !self.notebook.is_published(note) {
+                continue;
+            }
+            let markdown = note.read();
+            let headings = headings(&markdown);
+
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in terms(&plain_text(&markdown)) {
+                *counts.entry(term).or_insert(0) += 1;
+            }
+            for term in terms(note.title()) {
+                *counts.entry(term).or_insert(0) += TITLE_WEIGHT;
+            }
+            for term in headings.iter().flat_map(|h| terms(h)) {
+                *counts.entry(term).or_insert(0) += HEADING_WEIGHT;
+            }
+            for term in note.tags().iter().flat_map(|t| terms(t)) {
+                *counts.entry(term).or_insert(0) += TITLE_WEIGHT;
+            }
+
+            let doc = docs.len();
+            for (term, count) in counts {
+                index.entry(term).or_default().push((doc, count));
+            }
+            docs.push(format!("{{\"u\":{},\"t\":{},\"h\":{},\"g\":{}}}",
+                json::string(&format!("{}.html", id)),
+                json::string(note.title()),
+                json::string_array(&headings),
+                json::string_array(note.tags())));
+        }
+
+        let terms: Vec<String> = index.iter().map(|(term, postings)| {
+            let postings: Vec<String> = postings.iter()
+                .map(|(doc, count)| format!("{},{}", doc, count))
+                .collect();
+            format!("{}:[{}]", json::string(term), postings.join(","))
+        }).collect();
+
+        format!("{{\"stop\":{},\"docs\":[{}],\"terms\":{{{}}}}}",
+            json::string_array(STOP_WORDS), docs.join(","), terms.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stemming() {
+        for (word, stemmed) in [
+            ("studies", "study"), ("studied", "study"), ("study", "study"),
+            ("testing", "test"), ("tested", "test"), ("tests", "test"),
+            ("darkness", "dark"), ("management", "manage"), ("quickly", "quick"),
+            // Too short to strip, or not a plural
+            ("ties", "tie"), ("bed", "bed"), ("is", "is"),
+            ("class", "class"), ("status", "status"), ("analysis", "analysis"),
+        ] {
+            assert_eq!(stem(word), stemmed, "stem of {}", word);
+        }
+    }
+
+    #[test]
+    fn index_terms() {
+        assert_eq!(terms("The Studies of a Physicist, in 3D"),
+                   vec!["study", "physicist", "3d"]);
+    }
+
+    #[test]
+    fn heading_text() {
+        let markdown = "# One\n\ntext\n\n## Two `code`\n";
+        assert_eq!(headings(markdown), vec!["One", "Two code"]);
+    }
+}
@@ -0,0 +1,262 @@
+use pulldown_cmark::{Event, Options, Tag};
+use pulldown_cmark::Parser as CmarkParser;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use std::sync::OnceLock;
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::compiler::escape;
+use crate::listing::SortBy;
+
+/// Find inline `#tags` in markdown, skipping code and links. Tags can be
+/// hierarchical (`#course/physics`) and must contain a non-digit.
+pub fn find_tags(content: &str) -> Vec<String> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"(?:^|[\s(])#([\w][\w/-]*)").unwrap());
+
+    let mut tags = vec![];
+    // Depth of the links, images and code blocks around the current text
+    let mut skipped = 0;
+    for event in CmarkParser::new_ext(content, Options::empty()) {
+        match event {
+            Event::Start(Tag::Link(..)) | Event::Start(Tag::Image(..))
+                | Event::Start(Tag::CodeBlock(_)) => skipped += 1,
+            Event::End(Tag::Link(..)) | Event::End(Tag::Image(..))
+                | Event::End(Tag::CodeBlock(_)) => skipped -= 1,
+            Event::Text(text) if skipped == 0 => {
+                for cap in re.captures_iter(&text) {
+                    let tag = cap[1].trim_end_matches('/');
+                    if !tag.chars().all(|c| c.is_ascii_digit()) {
+                        tags.push(String::from(tag));
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    tags
+}
+
+/// Parse the `tags` front matter value, e.g. `[a, b]`, `a, b` or `#a #b`
+pub fn split_tags(input: &str) -> Vec<String> {
+    crate::split_yaml_list(input).iter()
+        .flat_map(|x| x.split_whitespace())
+        .map(|x| x.trim_start_matches('#').trim_end_matches('/'))
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Check whether `tag` is `query` or one of its subtags
+pub fn matches(tag: &str, query: &str) -> bool {
+    let query = query.trim_start_matches('#');
+    tag == query
+        || (tag.starts_with(query) && tag[query.len()..].starts_with('/'))
+}
+
+/// `tag` and all of its parents, e.g. `a/b/c`, `a/b` and `a`
+pub fn with_parents(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices('/').map(move |(i, _)| &tag[..i])
+        .chain(std::iter::once(tag))
+}
+
+/// Check whether `tag` can be used as a path in the `tags` directory, so
+/// every level is a plain name
+fn is_page_name(tag: &str) -> bool {
+    !tag.contains('\\') && tag.split('/').all(|level| {
+        let mut components = Path::new(level).components();
+        matches!((components.next(), components.next()),
+                 (Some(Component::Normal(_)), None))
+    })
+}
+
+/// Generates a page for every tag in the outdir's `tags` directory, and an
+/// overview with a tag cloud. Tags that are not a valid path, like `../x`,
+/// are skipped with a warning.
+pub struct TagPages<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> TagPages<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// {Tag, published notes with the tag or its subtags}
+    fn tags(&self) -> BTreeMap<&'a str, Vec<(&'a str, &'a Note)>> {
+        let mut tags: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+        for id in self.notebook.note_ids() {
+            let note = self.notebook.get(id).unwrap();
+            if !self.notebook.is_published(note) {
+                continue;
+            }
+
+            let mut note_tags: Vec<&str> = note.tags().iter()
+                .flat_map(|tag| with_parents(tag))
+                .collect();
+            note_tags.sort_unstable();
+            note_tags.dedup();
+            for tag in note_tags {
+                tags.entry(tag).or_default().push((id, note));
+            }
+        }
+        tags
+    }
+
+    pub fn write_all(&self) -> io::Result<()> {
+        let tagdir = self.notebook.outdir().join("tags");
+        let mut tags = self.tags();
+        tags.retain(|tag, _| {
+            let valid = is_page_name(tag);
+            if !valid {
+                println!("Warning: no page for the tag {}, which is not a \
+                         name in the outdir", tag);
+            }
+            valid
+        });
+
+        for (tag, notes) in &tags {
+            let outfile = tagdir.join(format!("{}.html", tag));
+            fs::create_dir_all(outfile.with_file_name(""))?;
+
+            let mut file = fs::File::create(&outfile)?;
+            file.write_all(self.render_tag(tag, notes.clone()).as_bytes())?;
+        }
+
+        let outfile = tagdir.join("index.html");
+        println!("Writing to {}", outfile.display());
+        fs::create_dir_all(&tagdir)?;
+        let mut file = fs::File::create(&outfile)?;
+        file.write_all(self.render_cloud(&tags).as_bytes())?;
+        Ok(())
+    }
+
+    /// Page listing all notes with `tag`
+    fn render_tag(&self, tag: &str, mut notes: Vec<(&str, &Note)>) -> String {
+        SortBy::Title.sort(&mut notes);
+
+        // Pages are in `tags/`, plus a directory per tag level
+        let depth = tag.matches('/').count() + 1;
+        let root = "../".repeat(depth);
+
+        let mut html = format!("<h1>#{}</h1>\n<ul class=\"index\">\n",
+                               escape(tag));
+        html.push_str(&format!(
+            "<li class=\"dir\"><a href=\"{}tags/index.html\">All tags</a></li>\n",
+            root));
+        for (id, note) in notes {
+            html.push_str(&format!("<li><a href=\"{}{}.html\">{}</a></li>\n",
+                root, escape(id), escape(note.title())));
+        }
+        html.push_str("</ul>\n");
+
+        self.notebook.compiler()
+            .to_page(&format!("#{}", tag), &html, depth, &[], self.notebook)
+    }
+
+    /// Overview of all tags, sized by the number of notes
+    fn render_cloud(&self, tags: &BTreeMap<&str, Vec<(&str, &Note)>>)
+        -> String
+    {
+        let max = tags.values().map(|x| x.len()).max().unwrap_or(1) as f32;
+
+        let mut html = String::from("<h1>Tags</h1>\n<p class=\"tag-cloud\">\n");
+        for (tag, notes) in tags {
+            let size = 0.8 + 1.2 * notes.len() as f32 / max;
+            html.push_str(&format!(
+                "<a href=\"{}.html\" style=\"font-size: {:.2}em\" \
+                 title=\"{} note(s)\">#{}</a>\n",
+                escape(tag), size, notes.len(), escape(tag)));
+        }
+        html.push_str("</p>\n");
+
+        self.notebook.compiler().to_page("Tags", &html, 1, &[], self.notebook)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn find() {
+        assert_eq!(find_tags("#start, mid #course/physics/waves. (#paren)"),
+                   vec!["start", "course/physics/waves", "paren"]);
+        assert_eq!(find_tags("a#word C#, #123 and #1st #trailing/"),
+                   vec!["1st", "trailing"]);
+        assert_eq!(find_tags("`#code` [#link](x) ![#image](x.png) #after"),
+                   vec!["after"]);
+        assert_eq!(find_tags("```\n#fenced\n```\n\n    #indented\n\n# Heading"),
+                   Vec::<String>::new());
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_tags("[a, b/c/]"), vec!["a", "b/c"]);
+        assert_eq!(split_tags("#a #b"), vec!["a", "b"]);
+        assert_eq!(split_tags("a, b"), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn matching() {
+        assert!(matches("course", "course"));
+        assert!(matches("course/physics", "course"));
+        assert!(matches("course/physics", "#course"));
+        assert!(!matches("courses", "course"));
+        assert!(!matches("course", "course/physics"));
+
+        assert_eq!(with_parents("a/b/c").collect::<Vec<_>>(), vec!["a", "a/b", "a/b/c"]);
+        assert_eq!(with_parents("a").collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn parents() {
+        let notebook = notebook(&[
+            ("one.md", "#course/physics/waves"),
+            ("two.md", "---\ntags: [course]\n---\n"),
+            ("draft.md", "---\ndraft: true\n---\n#course #secret"),
+        ]);
+
+        let tags: Vec<(&str, Vec<&str>)> = TagPages::new(&notebook).tags()
+            .into_iter()
+            .map(|(tag, notes)| {
+                let mut ids: Vec<&str> = notes.iter().map(|x| x.0).collect();
+                ids.sort_unstable();
+                (tag, ids)
+            })
+            .collect();
+        assert_eq!(tags, vec![
+            ("course", vec!["one", "two"]),
+            ("course/physics", vec!["one"]),
+            ("course/physics/waves", vec!["one"]),
+        ]);
+    }
+
+    #[test]
+    fn page_names() {
+        assert!(is_page_name("course/physics"));
+        assert!(is_page_name("v1.2"));
+        for tag in &["", "..", "../../x", "/etc/x", "a//b", "a/./b", "a\\b"] {
+            assert!(!is_page_name(tag), "{}", tag);
+        }
+
+        let notebook = notebook(&[
+            ("note.md", "---\ntags: [v1.2, ../../x, /etc/x]\n---\n"),
+        ]);
+        TagPages::new(&notebook).write_all().unwrap();
+
+        let tagdir = notebook.outdir().join("tags");
+        assert!(tagdir.join("v1.2.html").exists());
+        assert!(!tagdir.join("v1.html").exists());
+        assert!(!notebook.basedir().join("x.html").exists());
+
+        let cloud = fs::read_to_string(tagdir.join("index.html")).unwrap();
+        assert!(cloud.contains("<a href=\"v1.2.html\""));
+        assert!(!cloud.contains("x.html"));
+    }
+}
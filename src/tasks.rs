@@ -0,0 +1,320 @@
+use chrono::{Local, NaiveDate};
+use pulldown_cmark::{Event, Options, Tag};
+use pulldown_cmark::Parser as CmarkParser;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::fs;
+
+use crate::{Note, Notebook};
+use crate::check::Source;
+use crate::compiler::escape;
+use crate::links::block_marker;
+use crate::tags::find_tags;
+
+/// File name of the agenda page, in the root of the outdir
+pub const AGENDA_PAGE: &str = "agenda.html";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+impl Priority {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "high" => Some(Priority::High),
+            "medium" => Some(Priority::Medium),
+            "low" => Some(Priority::Low),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Medium => "medium",
+            Priority::Low => "low",
+        }
+    }
+}
+
+/// A `- [ ]` or `- [x]` list item in a note
+///
+/// The text can hold metadata: `due:2026-10-20` for the due date, `@name`
+/// for the people involved and `!high`, `!medium` or `!low` for the priority.
+/// `#tags` stay part of the text.
+pub struct Task<'a> {
+    pub note: &'a Note,
+    /// Text of the item, including the metadata but not the block id
+    pub text: String,
+    /// The `^block-id` at the end of the item, without `^`
+    pub block_id: Option<String>,
+    pub done: bool,
+    pub due: Option<NaiveDate>,
+    pub people: Vec<String>,
+    pub priority: Option<Priority>,
+    pub tags: Vec<String>,
+    /// Line and column of the checkbox in the note file
+    pub position: (usize, usize),
+    /// Number of the task in its note, starting at 1. The compiled note has
+    /// a `task-<number>` anchor at the task.
+    pub number: usize,
+}
+
+impl<'a> Task<'a> {
+    /// All tasks in `note`, in order. Tasks in nested lists are separate
+    /// tasks, and not part of the text of their parent.
+    pub fn find(note: &'a Note, options: Options) -> Vec<Self> {
+        let source = Source::read(note);
+        let mut output = vec![];
+        let mut current: Option<(bool, usize, String)> = None;
+
+        for (event, range) in CmarkParser::new_ext(&source.body, options).into_offset_iter() {
+            match event {
+                Event::TaskListMarker(done) =>
+                    current = Some((done, range.start, String::new())),
+                Event::Text(text) => {
+                    if let Some((_, _, task)) = current.as_mut() {
+                        task.push_str(&text);
+                    }
+                },
+                Event::Code(code) => {
+                    if let Some((_, _, task)) = current.as_mut() {
+                        task.push_str(&format!("`{}`", code));
+                    }
+                },
+                Event::SoftBreak | Event::HardBreak => {
+                    if let Some((_, _, task)) = current.as_mut() {
+                        task.push(' ');
+                    }
+                },
+                Event::Start(Tag::List(_)) | Event::End(Tag::Item) => {
+                    if let Some((done, offset, text)) = current.take() {
+                        let number = output.len() + 1;
+                        output.push(Self::parse(note, done, &text,
+                                                source.position(offset), number));
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        output
+    }
+
+    fn parse(note: &'a Note, done: bool, text: &str, position: (usize, usize),
+             number: usize) -> Self {
+        let text = text.trim();
+        let (text, block_id) = match block_marker(text) {
+            Some((offset, id)) => (&text[..offset], Some(String::from(id))),
+            None => (text, None),
+        };
+        let mut task = Self {
+            note,
+            text: String::from(text),
+            block_id,
+            done,
+            due: None,
+            people: vec![],
+            priority: None,
+            tags: find_tags(text),
+            position,
+            number,
+        };
+
+        for word in text.split_whitespace() {
+            let word = word.trim_end_matches([',', '.', ';', ':', ')']);
+            if let Some(date) = word.strip_prefix("due:") {
+                task.due = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok().or(task.due);
+            } else if let Some(name) = word.strip_prefix('@').filter(|x| !x.is_empty()) {
+                task.people.push(String::from(name));
+            } else if let Some(priority) = word.strip_prefix('!') {
+                task.priority = Priority::from_name(priority).or(task.priority);
+            }
+        }
+
+        task
+    }
+
+    /// The text without the due date, people and priority
+    pub fn description(&self) -> String {
+        self.text.split_whitespace()
+            .filter(|word| {
+                let word = word.trim_end_matches([',', '.', ';', ':', ')']);
+                !(word.strip_prefix("due:")
+                    .is_some_and(|x| NaiveDate::parse_from_str(x, "%Y-%m-%d").is_ok())
+                  || (word.starts_with('@') && word.len() > 1)
+                  || word.strip_prefix('!').and_then(Priority::from_name).is_some())
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Open and due before today
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.done && self.due.is_some_and(|due| due < today)
+    }
+}
+
+/// Writes a page with the open tasks of the published notes, grouped by due
+/// date, with links to the tasks in their notes
+pub struct Agenda<'a> {
+    notebook: &'a Notebook,
+}
+
+impl<'a> Agenda<'a> {
+    pub fn new(notebook: &'a Notebook) -> Self {
+        Self { notebook }
+    }
+
+    /// Write the agenda, also when there are no open tasks so an old one
+    /// does not stay around
+    pub fn write_all(&self) -> io::Result<()> {
+        let tasks: Vec<Task> = self.notebook.notes().published().tasks()
+            .into_iter()
+            .filter(|task| !task.done)
+            .collect();
+
+        let outfile = self.notebook.outdir().join(AGENDA_PAGE);
+        println!("Writing to {}", outfile.display());
+        fs::create_dir_all(self.notebook.outdir())?;
+        let page = self.notebook.compiler()
+            .to_page("Agenda", &self.render(tasks), 0, &[], self.notebook);
+        fs::File::create(&outfile)?.write_all(page.as_bytes())
+    }
+
+    /// A section per due date, and one for the tasks without a due date.
+    /// Tasks are sorted by priority within a section.
+    fn render(&self, tasks: Vec<Task>) -> String {
+        let today = Local::now().date_naive();
+        let mut dates: BTreeMap<Option<NaiveDate>, Vec<Task>> = BTreeMap::new();
+        for task in tasks {
+            dates.entry(task.due).or_default().push(task);
+        }
+
+        let mut html = String::from("<h1>Agenda</h1>\n");
+        if dates.is_empty() {
+            html.push_str("<p>No open tasks.</p>\n");
+        }
+        // `None` sorts first, but tasks without a date go last
+        let undated = dates.remove(&None);
+        let sections = dates.into_iter()
+            .chain(undated.map(|tasks| (None, tasks)));
+
+        for (date, mut tasks) in sections {
+            let heading = match date {
+                Some(date) if date < today => format!(
+                    "<h2 class=\"overdue\">{} (overdue)</h2>", date.format("%A %-d %B %Y")),
+                Some(date) if date == today => format!(
+                    "<h2 class=\"today\">Today, {}</h2>", date.format("%A %-d %B %Y")),
+                Some(date) => format!("<h2>{}</h2>", date.format("%A %-d %B %Y")),
+                None => String::from("<h2>No due date</h2>"),
+            };
+            html.push_str(&heading);
+            html.push_str("\n<ul class=\"agenda\">\n");
+
+            tasks.sort_by(|a, b| {
+                (a.priority.is_none(), a.priority, a.note.id(), a.number)
+                    .cmp(&(b.priority.is_none(), b.priority, b.note.id(), b.number))
+            });
+            for task in tasks {
+                html.push_str(&render_task(&task));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html
+    }
+}
+
+/// List item for a task on the agenda
+fn render_task(task: &Task) -> String {
+    let mut meta = vec![format!("<span class=\"task-note\">{}</span>",
+                                escape(task.note.title()))];
+    if let Some(priority) = task.priority {
+        meta.push(format!("<span class=\"priority-{0}\">!{0}</span>",
+                          priority.name()));
+    }
+    for person in &task.people {
+        meta.push(format!("<span class=\"person\">@{}</span>", escape(person)));
+    }
+
+    format!("<li><a href=\"{}.html#task-{}\">{}</a> {}</li>\n",
+            escape(task.note.id()), task.number, escape(&task.description()),
+            meta.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::notebook;
+
+    #[test]
+    fn find() {
+        let notebook = notebook(&[("todo.md", concat!(
+            "# Todo\n",
+            "\n",
+            "- [ ] Write `report` due:2026-10-20 @anna !high #work\n",
+            "  - [x] Outline, @bob\n",
+            "- plain item\n",
+            "- [ ] Call !urgent due:soon\n",
+            "\n",
+            "```\n- [ ] not a task\n```\n"))]);
+        let note = notebook.get("todo").unwrap();
+        let tasks = Task::find(note, Options::all());
+
+        assert_eq!(tasks.len(), 3);
+        let (write, outline, call) = (&tasks[0], &tasks[1], &tasks[2]);
+
+        assert_eq!(write.text, "Write `report` due:2026-10-20 @anna !high #work");
+        assert_eq!(write.description(), "Write `report` #work");
+        assert!(!write.done);
+        assert_eq!(write.due, NaiveDate::from_ymd_opt(2026, 10, 20));
+        assert_eq!(write.people, vec!["anna"]);
+        assert_eq!(write.priority, Some(Priority::High));
+        assert_eq!(write.tags, vec!["work"]);
+        assert_eq!((write.position, write.number), ((3, 3), 1));
+
+        assert_eq!(outline.text, "Outline, @bob");
+        assert!(outline.done);
+        assert_eq!(outline.people, vec!["bob"]);
+        assert_eq!((outline.position, outline.number), ((4, 5), 2));
+
+        // Unknown priorities and invalid dates stay part of the text
+        assert_eq!(call.due, None);
+        assert_eq!(call.priority, None);
+        assert_eq!(call.description(), "Call !urgent due:soon");
+    }
+
+    #[test]
+    fn block_ids() {
+        let notebook = notebook(&[("todo.md", concat!(
+            "- [ ] Pay ^rent due:2026-10-01\n",
+            "- [ ] Pay rent due:2026-10-01 ^rent-1\n",
+            "- [ ] Read ^id\n"))]);
+        let tasks = Task::find(notebook.get("todo").unwrap(), Options::all());
+
+        // Only a marker at the end of the text is a block id
+        assert_eq!(tasks[0].text, "Pay ^rent due:2026-10-01");
+        assert_eq!(tasks[0].description(), "Pay ^rent");
+        assert_eq!(tasks[0].block_id, None);
+        assert_eq!(tasks[1].text, "Pay rent due:2026-10-01");
+        assert_eq!(tasks[1].description(), "Pay rent");
+        assert_eq!(tasks[1].block_id.as_deref(), Some("rent-1"));
+        assert_eq!(tasks[2].description(), "Read");
+        assert_eq!(tasks[2].block_id.as_deref(), Some("id"));
+    }
+
+    #[test]
+    fn overdue() {
+        let notebook = notebook(&[("todo.md", "- [ ] a due:2026-10-19\n- [x] b due:2026-01-01\n")]);
+        let tasks = Task::find(notebook.get("todo").unwrap(), Options::all());
+        let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+
+        assert!(!tasks[0].is_overdue(day(19)));
+        assert!(tasks[0].is_overdue(day(20)));
+        assert!(!tasks[1].is_overdue(day(20)));
+    }
+}
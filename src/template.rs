@@ -0,0 +1,143 @@
+use chrono::Local;
+use chrono::format::{Item, StrftimeItems};
+use regex::{Captures, Regex};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::io;
+use std::fs;
+
+/// Template used by `notes new` when the templates directory has no
+/// `default` template
+const BUILTIN: &str = "---
+date: {{date}}
+---
+# {{title}}
+
+";
+
+/// A note template
+///
+/// Templates are notes with `{{variable}}` placeholders:
+///
+/// - `{{title}}`: title of the new note
+/// - `{{date}}` and `{{time}}`: current date and time, `{{date:%d %B %Y}}`
+///   uses a custom format
+/// - `{{uuid}}`: random UUID
+/// - `{{prompt:Question}}`: asks for a value, each question only once
+///
+/// Unknown variables and invalid date formats are left as they are.
+pub struct Template {
+    contents: String,
+    /// Extension of the template file, which new notes get as well
+    pub extension: String,
+}
+
+impl Template {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            contents: fs::read_to_string(path)?,
+            extension: path.extension().map_or_else(|| String::from("md"),
+                |x| x.to_string_lossy().to_string()),
+        })
+    }
+
+    /// The builtin markdown template
+    pub fn builtin() -> Self {
+        Self {
+            contents: String::from(BUILTIN),
+            extension: String::from("md"),
+        }
+    }
+
+    /// Fill in the variables. `prompt` is called with the question of each
+    /// `{{prompt:...}}` variable and returns the answer.
+    pub fn render<F>(&self, title: &str, mut prompt: F) -> String
+        where F: FnMut(&str) -> String
+    {
+        let re = Regex::new(r"\{\{\s*(\w+)(?::([^}]*))?\s*\}\}").unwrap();
+        let now = Local::now();
+        let mut answers: Vec<(String, String)> = vec![];
+
+        re.replace_all(&self.contents, |cap: &Captures| {
+            let arg = cap.get(2).map(|x| x.as_str().trim());
+            match (&cap[1], arg) {
+                ("title", _) => String::from(title),
+                ("date", None) => now.format("%Y-%m-%d").to_string(),
+                ("time", None) => now.format("%H:%M").to_string(),
+                ("date", Some(format)) | ("time", Some(format))
+                    if is_valid_format(format) => now.format(format).to_string(),
+                ("uuid", _) => uuid(),
+                ("prompt", Some(question)) => {
+                    if let Some((_, answer)) = answers.iter().find(|x| x.0 == question) {
+                        return answer.clone();
+                    }
+                    let answer = prompt(question);
+                    answers.push((String::from(question), answer.clone()));
+                    answer
+                },
+                _ => String::from(&cap[0]),
+            }
+        }).into_owned()
+    }
+}
+
+/// Check that `format` only has valid strftime specifiers. Formatting a date
+/// with an invalid one panics.
+pub fn is_valid_format(format: &str) -> bool {
+    StrftimeItems::new(format).all(|item| !matches!(item, Item::Error))
+}
+
+/// Random version 4 UUID
+fn uuid() -> String {
+    // Every `RandomState` is seeded with new random keys
+    let random = || RandomState::new().build_hasher().finish();
+    let (a, b) = (random(), random());
+
+    let a = (a & 0xffff_ffff_ffff_0fff) | 0x4000;
+    let b = (b & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}", a >> 32, (a >> 16) & 0xffff,
+            a & 0xffff, b >> 48, b & 0xffff_ffff_ffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(contents: &str) -> Template {
+        Template { contents: String::from(contents), extension: String::from("md") }
+    }
+
+    #[test]
+    fn variables() {
+        let mut questions = vec![];
+        let output = template("# {{ title }}\n{{prompt:Who?}} {{prompt:Who?}} {{unknown}}\n")
+            .render("A note", |question| {
+                questions.push(String::from(question));
+                String::from("me")
+            });
+        assert_eq!(output, "# A note\nme me {{unknown}}\n");
+        assert_eq!(questions, vec!["Who?"]);
+    }
+
+    #[test]
+    fn dates() {
+        let output = template("{{date}} {{date:%%}} {{time:%Q}}").render("", |_| String::new());
+        let (date, rest) = output.split_once(' ').unwrap();
+        assert!(chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok());
+        assert_eq!(rest, "% {{time:%Q}}");
+
+        assert!(is_valid_format("%Y-%m-%d %H:%M"));
+        assert!(!is_valid_format("%Q"));
+        assert!(!is_valid_format("100%"));
+    }
+
+    #[test]
+    fn uuids() {
+        let (a, b) = (uuid(), uuid());
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+        assert!(["8", "9", "a", "b"].contains(&&a[19..20]));
+    }
+}